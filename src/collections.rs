@@ -1,8 +1,9 @@
 use indexmap::{Equivalent, IndexMap};
 use std::{
     borrow::Borrow,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     hash::{BuildHasher, Hash},
+    iter::Sum,
 };
 use thiserror::Error;
 
@@ -34,6 +35,21 @@ pub trait MapExt<K, Q: ?Sized = K> {
     fn replace_key(&mut self, k1: &Q, k2: K) -> Result<(), ReplaceKeyErr>
     where
         K: Borrow<Q>;
+
+    /// Split the map in two at the given key, returning the entries at and after it.
+    ///
+    /// For `BTreeMap`, this follows the range semantics of
+    /// [`BTreeMap::split_off`](https://doc.rust-lang.org/stable/std/collections/struct.BTreeMap.html#method.split_off),
+    /// so `key` doesn't have to exist.
+    ///
+    /// For `IndexMap`, the entries from the key's index to the end are split off, keeping their
+    /// order. If the key does not exist, nothing is split off.
+    ///
+    /// `HashMap` has no ordering, so splitting is not supported: every entry is moved into the
+    /// returned map and `self` is left empty.
+    fn split_off_key(&mut self, key: &Q) -> Self
+    where
+        Self: Default;
 }
 
 impl<K, Q, V, S> MapExt<K, Q> for HashMap<K, V, S>
@@ -59,6 +75,13 @@ where
         self.insert(k2, v);
         Ok(())
     }
+
+    fn split_off_key(&mut self, _key: &Q) -> Self
+    where
+        Self: Default,
+    {
+        std::mem::take(self)
+    }
 }
 
 impl<K, Q, V> MapExt<K, Q> for BTreeMap<K, V>
@@ -83,6 +106,10 @@ where
         self.insert(k2, v);
         Ok(())
     }
+
+    fn split_off_key(&mut self, key: &Q) -> Self {
+        self.split_off(key)
+    }
 }
 
 impl<K, Q, V, S> MapExt<K, Q> for IndexMap<K, V, S>
@@ -115,123 +142,2935 @@ where
         self.swap_indices(i, j);
         Ok(())
     }
+
+    fn split_off_key(&mut self, key: &Q) -> Self
+    where
+        Self: Default,
+    {
+        let mut tail = Self::default();
+
+        if let Some(i) = self.get_index_of(key) {
+            tail.extend(self.drain(i..));
+        }
+
+        tail
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[cfg(feature = "json")]
+fn entries_to_json<'a, K, V>(
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+) -> serde_json::Result<serde_json::Value>
+where
+    K: serde::Serialize + 'a,
+    V: serde::Serialize + 'a,
+{
+    use serde_json::{to_value, Value};
 
-    #[test]
-    fn replace_key_hashmap() {
-        let mut map = HashMap::new();
+    let entries = entries
+        .map(|(k, v)| Ok((to_value(k)?, to_value(v)?)))
+        .collect::<serde_json::Result<Vec<_>>>()?;
 
-        map.insert("k1".to_string(), 123);
-        map.insert("k2".to_string(), 456);
+    Ok(if entries.iter().all(|(k, _)| k.is_string()) {
+        Value::Object(
+            entries
+                .into_iter()
+                .map(|(k, v)| match k {
+                    Value::String(k) => (k, v),
+                    _ => unreachable!(),
+                })
+                .collect(),
+        )
+    } else {
+        Value::Array(
+            entries
+                .into_iter()
+                .map(|(k, v)| Value::Array(vec![k, v]))
+                .collect(),
+        )
+    })
+}
 
-        assert_eq!(map["k1"], 123);
-        assert_eq!(map["k2"], 456);
-        assert_eq!(
-            map.replace_key("k3", "k2".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
-        assert_eq!(
-            map.replace_key("k3", "k3".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
-        assert_eq!(
-            map.replace_key("k3", "k4".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
+/// Some general extensions to `Maps` that don't involve borrowed lookups.
+///
+/// These are kept apart from [`MapExt`] and [`MapLookup`], because those are generic over the
+/// borrowed key type `Q`, which could not be inferred for methods that don't take one. All of
+/// them are in [`prelude`](crate::prelude), so a single `use est::prelude::*;` brings them
+/// into scope.
+///
+/// Only the methods that build a new map need a `Default` hasher, e.g. through
+/// `Self: Default`. The others work with any `S: BuildHasher`.
+pub trait MapOps<K, V> {
+    /// The same kind of map, with keys of type `K2` and values of type `V2`.
+    type MapOf<K2, V2>;
 
-        let cloned = map.clone();
-        assert_eq!(map.replace_key("k1", "k1".to_string()), Ok(()));
-        assert_eq!(map, cloned);
+    /// Transform every value with a fallible function, keeping the keys.
+    ///
+    /// Stops at the first error and returns it. The order of `IndexMap` and `BTreeMap`
+    /// is preserved.
+    fn try_map_values<V2, E, F>(self, f: F) -> Result<Self::MapOf<K, V2>, E>
+    where
+        F: FnMut(V) -> Result<V2, E>,
+        Self::MapOf<K, V2>: Default;
 
-        assert_eq!(
-            map.replace_key("k1", "k2".to_string()),
-            Err(ReplaceKeyErr::NewKeyOccupied)
-        );
-        assert_eq!(map.replace_key("k1", "k3".to_string()), Ok(()));
-        assert!(!map.contains_key("k1"));
-        assert_eq!(map["k3"], 123);
-        assert_eq!(map["k2"], 456);
+    /// Keys that are present in `self` but not in `other`.
+    ///
+    /// The keys follow the iteration order of `self` (which is sorted for `BTreeMap`).
+    fn keys_only_in<'a>(&'a self, other: &'a Self) -> Vec<&'a K>;
+
+    /// Keys that are present in both `self` and `other`.
+    ///
+    /// The keys follow the iteration order of `self` (which is sorted for `BTreeMap`).
+    fn common_keys<'a>(&'a self, other: &'a Self) -> Vec<&'a K>;
+
+    /// Replace several keys in order, applying whatever can be applied.
+    ///
+    /// Each pair `(old, new)` is attempted with the same rules as [`MapExt::replace_key`].
+    /// Successful replacements take effect immediately, and failed ones are collected
+    /// as `(old, new, err)` and returned.
+    fn replace_keys_best_effort(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, K)>,
+    ) -> Vec<(K, K, ReplaceKeyErr)>;
+
+    /// Iterate over the values of `self` and `other` paired by key.
+    ///
+    /// Only keys present in both maps are yielded, following the iteration order of `self`.
+    fn zip_values<'a, V2>(
+        &'a self,
+        other: &'a Self::MapOf<K, V2>,
+    ) -> impl Iterator<Item = (&'a K, &'a V, &'a V2)>
+    where
+        K: 'a,
+        V: 'a,
+        V2: 'a;
+
+    /// Sum all the values.
+    fn sum_values<T: Sum<V>>(&self) -> T
+    where
+        V: Clone;
+
+    /// The arithmetic mean of all the values, or `None` if the map is empty.
+    fn mean_values(&self) -> Option<f64>
+    where
+        V: Into<f64> + Clone;
+
+    /// The entry with the greatest value, or `None` if the map is empty.
+    ///
+    /// Ties are broken deterministically in favor of the smallest key.
+    fn max_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord;
+
+    /// The entry with the least value, or `None` if the map is empty.
+    ///
+    /// Ties are broken deterministically in favor of the smallest key.
+    fn min_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord;
+
+    /// Apply a sparse patch onto the map.
+    ///
+    /// For each entry of `patch`, `Some(v)` sets (or overwrites) the key, and `None` removes it.
+    /// For `IndexMap`, overwritten keys keep their positions, new keys are appended, and
+    /// removals preserve the order of the remaining entries.
+    fn apply_patch(&mut self, patch: Self::MapOf<K, Option<V>>);
+
+    /// Shorten the map to at most `len` entries, returning the removed ones.
+    ///
+    /// For `IndexMap`, the entries past index `len` are removed, in order. For `BTreeMap`,
+    /// the `len` smallest keys are kept, and the rest are returned in ascending order.
+    ///
+    /// `HashMap` has no ordering, so arbitrary entries are removed.
+    ///
+    /// Does nothing if `len` is not less than the current length.
+    fn truncate_take(&mut self, len: usize) -> Vec<(K, V)>;
+
+    /// Fold all the values into an accumulator, in the natural order of the map.
+    fn fold_values<B, F: FnMut(B, &V) -> B>(&self, init: B, f: F) -> B;
+
+    /// Apply `modify` to the value of `key` if it exists, otherwise insert `default()`.
+    ///
+    /// `modify` is *not* called on a freshly inserted default value.
+    fn modify_or_insert<F: FnOnce(&mut V), D: FnOnce() -> V>(
+        &mut self,
+        key: K,
+        modify: F,
+        default: D,
+    );
+
+    /// Replace the prefix `old_prefix` with `new_prefix` in every key that starts with it,
+    /// returning the number of renamed keys.
+    ///
+    /// All the keys are renamed at once. If any new key would collide with an existing key
+    /// that is not renamed itself, `Err(ReplaceKeyErr::NewKeyOccupied)` is returned and the map
+    /// is left untouched. The order of `IndexMap` is preserved.
+    fn rename_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize, ReplaceKeyErr>
+    where
+        K: AsRef<str> + From<String>;
+
+    /// Call `f` on every entry in sorted key order, with mutable access to the values.
+    ///
+    /// This gives deterministic mutation even for `HashMap`.
+    fn for_each_mut_sorted<F: FnMut(&K, &mut V)>(&mut self, f: F)
+    where
+        K: Ord;
+
+    /// Dump the map into a `serde_json::Value`, for quick inspection.
+    ///
+    /// If every key serializes to a string, the result is an object (keeping the order of
+    /// `IndexMap` and `BTreeMap`). Otherwise, it is an array of `[key, value]` pairs.
+    #[cfg(feature = "json")]
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize;
+
+    /// Create an empty map pre-sized to hold `other.len()` entries.
+    ///
+    /// `BTreeMap` has no notion of capacity, so this is just `BTreeMap::new()` there.
+    fn with_capacity_like(other: &Self) -> Self
+    where
+        Self: Default;
+
+    /// Remove every entry whose value already appeared earlier in the natural order of the map,
+    /// returning the removed entries in that order.
+    ///
+    /// For `IndexMap`, the entry that is kept is the earliest inserted one; for `BTreeMap`, the
+    /// one with the smallest key. `HashMap` has no ordering, so an arbitrary one is kept.
+    fn dedup_values(&mut self) -> Vec<(K, V)>
+    where
+        V: Eq + Hash;
+
+    /// Iterate over the entries in sorted key order, without consuming the map.
+    ///
+    /// This gives reproducible output for `HashMap`, e.g. in snapshot tests.
+    fn sorted_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: Ord + 'a,
+        V: 'a;
+
+    /// Shrink the capacity of the map as much as possible, like `shrink_to_fit`.
+    ///
+    /// `BTreeMap` has no notion of capacity, so this does nothing there.
+    fn shrink(&mut self);
+
+    /// Keep only the keys also present in `other`, calling `f` to fold `other`'s value into
+    /// each kept one.
+    ///
+    /// The order of `IndexMap` is preserved.
+    fn intersect_with<V2, F: FnMut(&K, &mut V, &V2)>(&mut self, other: &Self::MapOf<K, V2>, f: F);
+
+    /// Take all the entries out, leaving an empty map behind, like
+    /// [`std::mem::take`](https://doc.rust-lang.org/stable/std/mem/fn.take.html).
+    ///
+    /// The returned map keeps the original allocation.
+    fn take(&mut self) -> Self
+    where
+        Self: Default,
+    {
+        std::mem::take(self)
     }
 
-    #[test]
-    fn replace_key_btreemap() {
-        let mut map = BTreeMap::new();
+    /// Reserve capacity for at least `additional` more entries, like `reserve`.
+    ///
+    /// `BTreeMap` has no notion of capacity, so this does nothing there.
+    fn reserve_for(&mut self, additional: usize);
 
-        map.insert("k1".to_string(), 123);
-        map.insert("k2".to_string(), 456);
+    /// Returns `true` if every entry of `self` is also in `other`, with an equal value.
+    fn is_subset_of(&self, other: &Self) -> bool
+    where
+        V: PartialEq;
 
-        assert_eq!(map["k1"], 123);
-        assert_eq!(map["k2"], 456);
-        assert_eq!(
-            map.replace_key("k3", "k2".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
-        assert_eq!(
-            map.replace_key("k3", "k3".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
-        assert_eq!(
-            map.replace_key("k3", "k4".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
+    /// Returns `true` if every entry of `other` is also in `self`, with an equal value.
+    fn is_superset_of(&self, other: &Self) -> bool
+    where
+        V: PartialEq,
+    {
+        other.is_subset_of(self)
+    }
 
-        let cloned = map.clone();
-        assert_eq!(map.replace_key("k1", "k1".to_string()), Ok(()));
-        assert_eq!(map, cloned);
+    /// Group the entries by the category returned by `key_fn`, and fold each group into an
+    /// accumulator starting from `init()`.
+    ///
+    /// The categories are ordered by their first appearance, in the natural order of the map.
+    fn fold_grouped<C, B, FK, FD, FF>(&self, key_fn: FK, init: FD, fold: FF) -> IndexMap<C, B>
+    where
+        C: Eq + Hash,
+        FK: FnMut(&K, &V) -> C,
+        FD: Fn() -> B,
+        FF: FnMut(&mut B, &K, &V);
 
-        assert_eq!(
-            map.replace_key("k1", "k2".to_string()),
-            Err(ReplaceKeyErr::NewKeyOccupied)
-        );
-        assert_eq!(map.replace_key("k1", "k3".to_string()), Ok(()));
-        assert!(!map.contains_key("k1"));
-        assert_eq!(map["k3"], 123);
-        assert_eq!(map["k2"], 456);
+    /// Return the first `Some` produced by `f`, in the natural order of the map.
+    fn find_map_value<R, F: FnMut(&K, &V) -> Option<R>>(&self, f: F) -> Option<R>;
+
+    /// Count the entries per category returned by `f`.
+    ///
+    /// The categories are ordered by their first appearance, like
+    /// [`fold_grouped`](Self::fold_grouped).
+    fn count_by<C: Eq + Hash, F: FnMut(&K, &V) -> C>(&self, f: F) -> IndexMap<C, usize> {
+        self.fold_grouped(f, || 0, |count, _, _| *count += 1)
     }
 
-    #[test]
-    fn replace_key_indexmap() {
-        let mut map = indexmap::indexmap! {
-            "k1".to_string() => 123,
-            "k2".to_string() => 456
+    /// Pass every key through `normalize`, e.g. to lowercase string keys, combining the
+    /// values of colliding keys with `resolve(earlier, later)`.
+    ///
+    /// For `IndexMap`, each normalized key keeps the position where it was first seen.
+    fn normalize_keys<F: FnMut(&K) -> K, R: FnMut(V, V) -> V>(
+        self,
+        normalize: F,
+        resolve: R,
+    ) -> Self;
+
+    /// Get the value of `key`, inserting the result of `f` if it doesn't exist.
+    ///
+    /// `f` is only called if the key doesn't exist. If it fails, the error is returned and
+    /// the map is left unchanged.
+    fn entry_or_try_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, E>;
+
+    /// Split the map into its keys and its values, in the natural order of the map, so that
+    /// the i-th key goes with the i-th value.
+    fn into_keys_values(self) -> (Vec<K>, Vec<V>);
+
+    /// Insert `value` only if `key` doesn't exist yet, returning whether it was inserted.
+    ///
+    /// An existing value is left untouched.
+    fn insert_if_absent(&mut self, key: K, value: V) -> bool;
+
+    /// Split the map into sub-maps by the category returned by `f`.
+    ///
+    /// The categories are ordered by their first appearance, like
+    /// [`fold_grouped`](Self::fold_grouped), and each sub-map keeps the natural order of its
+    /// entries.
+    fn into_grouped<C: Eq + Hash, F: FnMut(&K, &V) -> C>(self, f: F) -> IndexMap<C, Self>
+    where
+        Self: Default;
+}
+
+impl<K, V, S> MapOps<K, V> for HashMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    type MapOf<K2, V2> = HashMap<K2, V2, S>;
+
+    fn try_map_values<V2, E, F>(self, mut f: F) -> Result<HashMap<K, V2, S>, E>
+    where
+        F: FnMut(V) -> Result<V2, E>,
+        HashMap<K, V2, S>: Default,
+    {
+        let mut out = HashMap::default();
+        out.reserve(self.len());
+
+        for (k, v) in self {
+            out.insert(k, f(v)?);
+        }
+
+        Ok(out)
+    }
+
+    fn keys_only_in<'a>(&'a self, other: &'a Self) -> Vec<&'a K> {
+        self.keys().filter(|k| !other.contains_key(*k)).collect()
+    }
+
+    fn common_keys<'a>(&'a self, other: &'a Self) -> Vec<&'a K> {
+        self.keys().filter(|k| other.contains_key(*k)).collect()
+    }
+
+    fn replace_keys_best_effort(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, K)>,
+    ) -> Vec<(K, K, ReplaceKeyErr)> {
+        let mut failures = Vec::new();
+
+        for (old, new) in pairs {
+            let err = if !self.contains_key(&old) {
+                ReplaceKeyErr::OldKeyNotExist
+            } else if old != new && self.contains_key(&new) {
+                ReplaceKeyErr::NewKeyOccupied
+            } else {
+                self.replace_key(&old, new)
+                    .expect("this should be unreachable");
+                continue;
+            };
+
+            failures.push((old, new, err));
+        }
+
+        failures
+    }
+
+    fn zip_values<'a, V2>(
+        &'a self,
+        other: &'a HashMap<K, V2, S>,
+    ) -> impl Iterator<Item = (&'a K, &'a V, &'a V2)>
+    where
+        K: 'a,
+        V: 'a,
+        V2: 'a,
+    {
+        self.iter()
+            .filter_map(|(k, v)| other.get(k).map(|v2| (k, v, v2)))
+    }
+
+    fn sum_values<T: Sum<V>>(&self) -> T
+    where
+        V: Clone,
+    {
+        self.values().cloned().sum()
+    }
+
+    fn mean_values(&self) -> Option<f64>
+    where
+        V: Into<f64> + Clone,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let sum: f64 = self.values().cloned().map(Into::into).sum();
+        Some(sum / self.len() as f64)
+    }
+
+    fn max_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord,
+    {
+        self.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+    }
+
+    fn min_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord,
+    {
+        self.iter()
+            .min_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+    }
+
+    fn apply_patch(&mut self, patch: Self::MapOf<K, Option<V>>) {
+        for (k, v) in patch {
+            match v {
+                Some(v) => {
+                    self.insert(k, v);
+                }
+                None => {
+                    self.remove(&k);
+                }
+            }
+        }
+    }
+
+    fn truncate_take(&mut self, len: usize) -> Vec<(K, V)> {
+        if len >= self.len() {
+            return Vec::new();
+        }
+
+        let mut entries: Vec<_> = self.drain().collect();
+        let removed = entries.split_off(len);
+
+        self.extend(entries);
+        removed
+    }
+
+    fn fold_values<B, F: FnMut(B, &V) -> B>(&self, init: B, f: F) -> B {
+        self.values().fold(init, f)
+    }
+
+    fn modify_or_insert<F: FnOnce(&mut V), D: FnOnce() -> V>(
+        &mut self,
+        key: K,
+        modify: F,
+        default: D,
+    ) {
+        self.entry(key).and_modify(modify).or_insert_with(default);
+    }
+
+    fn rename_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize, ReplaceKeyErr>
+    where
+        K: AsRef<str> + From<String>,
+    {
+        let rename = |k: &K| {
+            k.as_ref()
+                .strip_prefix(old_prefix)
+                .map(|rest| format!("{new_prefix}{rest}"))
         };
+        let mut count = 0;
 
-        assert_eq!(map["k1"], 123);
-        assert_eq!(map["k2"], 456);
-        assert_eq!(map.get_index_of("k1"), Some(0));
-        assert_eq!(map.get_index(0), Some((&"k1".to_string(), &123)));
+        for k in self.keys() {
+            if let Some(new) = rename(k) {
+                if !new.starts_with(old_prefix) && self.contains_key(&K::from(new)) {
+                    return Err(ReplaceKeyErr::NewKeyOccupied);
+                }
 
-        assert_eq!(
-            map.replace_key("k3", "k2".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
-        assert_eq!(
-            map.replace_key("k3", "k3".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
-        assert_eq!(
-            map.replace_key("k3", "k4".to_string()),
-            Err(ReplaceKeyErr::OldKeyNotExist)
-        );
+                count += 1;
+            }
+        }
 
-        let cloned = map.clone();
-        assert_eq!(map.replace_key("k1", "k1".to_string()), Ok(()));
-        assert_eq!(map, cloned);
+        if count != 0 {
+            let entries: Vec<_> = self.drain().collect();
 
-        assert_eq!(
-            map.replace_key("k1", "k2".to_string()),
-            Err(ReplaceKeyErr::NewKeyOccupied)
-        );
+            self.extend(entries.into_iter().map(|(k, v)| match rename(&k) {
+                Some(new) => (K::from(new), v),
+                None => (k, v),
+            }));
+        }
 
-        assert_eq!(map.replace_key("k1", "k3".to_string()), Ok(()));
-        assert!(!map.contains_key("k1"));
-        assert_eq!(map["k3"], 123);
-        assert_eq!(map["k2"], 456);
-        assert_eq!(map.get_index_of("k3"), Some(0));
-        assert_eq!(map.get_index(0), Some((&"k3".to_string(), &123)));
+        Ok(count)
+    }
+
+    fn for_each_mut_sorted<F: FnMut(&K, &mut V)>(&mut self, mut f: F)
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<_> = self.iter_mut().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter().for_each(|(k, v)| f(k, v));
+    }
+
+    #[cfg(feature = "json")]
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        entries_to_json(self.iter())
+    }
+
+    fn with_capacity_like(other: &Self) -> Self
+    where
+        Self: Default,
+    {
+        let mut map = Self::default();
+        map.reserve(other.len());
+        map
+    }
+
+    fn dedup_values(&mut self) -> Vec<(K, V)>
+    where
+        V: Eq + Hash,
+    {
+        let entries = self.drain().collect();
+        dedup_entries(entries, self)
+    }
+
+    fn sorted_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: Ord + 'a,
+        V: 'a,
+    {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter()
+    }
+
+    fn shrink(&mut self) {
+        self.shrink_to_fit();
+    }
+
+    fn intersect_with<V2, F: FnMut(&K, &mut V, &V2)>(
+        &mut self,
+        other: &Self::MapOf<K, V2>,
+        mut f: F,
+    ) {
+        self.retain(|k, v| other.get(k).map(|v2| f(k, v, v2)).is_some());
+    }
+
+    fn reserve_for(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+
+    fn is_subset_of(&self, other: &Self) -> bool
+    where
+        V: PartialEq,
+    {
+        self.len() <= other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+
+    fn fold_grouped<C, B, FK, FD, FF>(&self, key_fn: FK, init: FD, fold: FF) -> IndexMap<C, B>
+    where
+        C: Eq + Hash,
+        FK: FnMut(&K, &V) -> C,
+        FD: Fn() -> B,
+        FF: FnMut(&mut B, &K, &V),
+    {
+        fold_entries_grouped(self.iter(), key_fn, init, fold)
+    }
+
+    fn find_map_value<R, F: FnMut(&K, &V) -> Option<R>>(&self, mut f: F) -> Option<R> {
+        self.iter().find_map(|(k, v)| f(k, v))
+    }
+
+    fn normalize_keys<F: FnMut(&K) -> K, R: FnMut(V, V) -> V>(
+        mut self,
+        mut normalize: F,
+        mut resolve: R,
+    ) -> Self {
+        let entries: Vec<_> = self.drain().collect();
+
+        for (k, v) in entries {
+            let k = normalize(&k);
+            let v = match self.remove(&k) {
+                Some(prev) => resolve(prev, v),
+                None => v,
+            };
+
+            self.insert(k, v);
+        }
+
+        self
+    }
+
+    fn entry_or_try_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, E> {
+        Ok(match self.entry(key) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => entry.insert(f()?),
+        })
+    }
+
+    fn into_keys_values(self) -> (Vec<K>, Vec<V>) {
+        self.into_iter().unzip()
+    }
+
+    fn insert_if_absent(&mut self, key: K, value: V) -> bool {
+        match self.entry(key) {
+            std::collections::hash_map::Entry::Occupied(_) => false,
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(value);
+                true
+            }
+        }
+    }
+
+    fn into_grouped<C: Eq + Hash, F: FnMut(&K, &V) -> C>(self, f: F) -> IndexMap<C, Self>
+    where
+        Self: Default,
+    {
+        group_entries(self, f)
+    }
+}
+
+impl<K, V> MapOps<K, V> for BTreeMap<K, V>
+where
+    K: Ord,
+{
+    type MapOf<K2, V2> = BTreeMap<K2, V2>;
+
+    fn try_map_values<V2, E, F>(self, mut f: F) -> Result<BTreeMap<K, V2>, E>
+    where
+        F: FnMut(V) -> Result<V2, E>,
+        BTreeMap<K, V2>: Default,
+    {
+        self.into_iter().map(|(k, v)| Ok((k, f(v)?))).collect()
+    }
+
+    fn keys_only_in<'a>(&'a self, other: &'a Self) -> Vec<&'a K> {
+        self.keys().filter(|k| !other.contains_key(*k)).collect()
+    }
+
+    fn common_keys<'a>(&'a self, other: &'a Self) -> Vec<&'a K> {
+        self.keys().filter(|k| other.contains_key(*k)).collect()
+    }
+
+    fn replace_keys_best_effort(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, K)>,
+    ) -> Vec<(K, K, ReplaceKeyErr)> {
+        let mut failures = Vec::new();
+
+        for (old, new) in pairs {
+            let err = if !self.contains_key(&old) {
+                ReplaceKeyErr::OldKeyNotExist
+            } else if old != new && self.contains_key(&new) {
+                ReplaceKeyErr::NewKeyOccupied
+            } else {
+                self.replace_key(&old, new)
+                    .expect("this should be unreachable");
+                continue;
+            };
+
+            failures.push((old, new, err));
+        }
+
+        failures
+    }
+
+    fn zip_values<'a, V2>(
+        &'a self,
+        other: &'a BTreeMap<K, V2>,
+    ) -> impl Iterator<Item = (&'a K, &'a V, &'a V2)>
+    where
+        K: 'a,
+        V: 'a,
+        V2: 'a,
+    {
+        self.iter()
+            .filter_map(|(k, v)| other.get(k).map(|v2| (k, v, v2)))
+    }
+
+    fn sum_values<T: Sum<V>>(&self) -> T
+    where
+        V: Clone,
+    {
+        self.values().cloned().sum()
+    }
+
+    fn mean_values(&self) -> Option<f64>
+    where
+        V: Into<f64> + Clone,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let sum: f64 = self.values().cloned().map(Into::into).sum();
+        Some(sum / self.len() as f64)
+    }
+
+    fn max_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord,
+    {
+        self.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+    }
+
+    fn min_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord,
+    {
+        self.iter()
+            .min_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+    }
+
+    fn apply_patch(&mut self, patch: Self::MapOf<K, Option<V>>) {
+        for (k, v) in patch {
+            match v {
+                Some(v) => {
+                    self.insert(k, v);
+                }
+                None => {
+                    self.remove(&k);
+                }
+            }
+        }
+    }
+
+    fn truncate_take(&mut self, len: usize) -> Vec<(K, V)> {
+        let mut removed = Vec::new();
+
+        while self.len() > len {
+            removed.extend(self.pop_last());
+        }
+
+        removed.reverse();
+        removed
+    }
+
+    fn fold_values<B, F: FnMut(B, &V) -> B>(&self, init: B, f: F) -> B {
+        self.values().fold(init, f)
+    }
+
+    fn modify_or_insert<F: FnOnce(&mut V), D: FnOnce() -> V>(
+        &mut self,
+        key: K,
+        modify: F,
+        default: D,
+    ) {
+        self.entry(key).and_modify(modify).or_insert_with(default);
+    }
+
+    fn rename_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize, ReplaceKeyErr>
+    where
+        K: AsRef<str> + From<String>,
+    {
+        let rename = |k: &K| {
+            k.as_ref()
+                .strip_prefix(old_prefix)
+                .map(|rest| format!("{new_prefix}{rest}"))
+        };
+        let mut count = 0;
+
+        for k in self.keys() {
+            if let Some(new) = rename(k) {
+                if !new.starts_with(old_prefix) && self.contains_key(&K::from(new)) {
+                    return Err(ReplaceKeyErr::NewKeyOccupied);
+                }
+
+                count += 1;
+            }
+        }
+
+        if count != 0 {
+            let entries: Vec<_> = std::mem::take(self).into_iter().collect();
+
+            self.extend(entries.into_iter().map(|(k, v)| match rename(&k) {
+                Some(new) => (K::from(new), v),
+                None => (k, v),
+            }));
+        }
+
+        Ok(count)
+    }
+
+    fn for_each_mut_sorted<F: FnMut(&K, &mut V)>(&mut self, mut f: F) {
+        self.iter_mut().for_each(|(k, v)| f(k, v));
+    }
+
+    #[cfg(feature = "json")]
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        entries_to_json(self.iter())
+    }
+
+    fn with_capacity_like(_other: &Self) -> Self
+    where
+        Self: Default,
+    {
+        Self::new()
+    }
+
+    fn dedup_values(&mut self) -> Vec<(K, V)>
+    where
+        V: Eq + Hash,
+    {
+        let entries = std::mem::take(self).into_iter().collect();
+        dedup_entries(entries, self)
+    }
+
+    fn sorted_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter()
+    }
+
+    fn shrink(&mut self) {}
+
+    fn intersect_with<V2, F: FnMut(&K, &mut V, &V2)>(
+        &mut self,
+        other: &Self::MapOf<K, V2>,
+        mut f: F,
+    ) {
+        self.retain(|k, v| other.get(k).map(|v2| f(k, v, v2)).is_some());
+    }
+
+    fn reserve_for(&mut self, _additional: usize) {}
+
+    fn is_subset_of(&self, other: &Self) -> bool
+    where
+        V: PartialEq,
+    {
+        self.len() <= other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+
+    fn fold_grouped<C, B, FK, FD, FF>(&self, key_fn: FK, init: FD, fold: FF) -> IndexMap<C, B>
+    where
+        C: Eq + Hash,
+        FK: FnMut(&K, &V) -> C,
+        FD: Fn() -> B,
+        FF: FnMut(&mut B, &K, &V),
+    {
+        fold_entries_grouped(self.iter(), key_fn, init, fold)
+    }
+
+    fn find_map_value<R, F: FnMut(&K, &V) -> Option<R>>(&self, mut f: F) -> Option<R> {
+        self.iter().find_map(|(k, v)| f(k, v))
+    }
+
+    fn normalize_keys<F: FnMut(&K) -> K, R: FnMut(V, V) -> V>(
+        self,
+        mut normalize: F,
+        mut resolve: R,
+    ) -> Self {
+        let mut out = BTreeMap::<K, Option<V>>::new();
+
+        for (k, v) in self {
+            let slot = out.entry(normalize(&k)).or_default();
+            *slot = Some(match slot.take() {
+                Some(prev) => resolve(prev, v),
+                None => v,
+            });
+        }
+
+        out.into_iter().filter_map(|(k, v)| Some((k, v?))).collect()
+    }
+
+    fn entry_or_try_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, E> {
+        Ok(match self.entry(key) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => entry.insert(f()?),
+        })
+    }
+
+    fn into_keys_values(self) -> (Vec<K>, Vec<V>) {
+        self.into_iter().unzip()
+    }
+
+    fn insert_if_absent(&mut self, key: K, value: V) -> bool {
+        match self.entry(key) {
+            std::collections::btree_map::Entry::Occupied(_) => false,
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                entry.insert(value);
+                true
+            }
+        }
+    }
+
+    fn into_grouped<C: Eq + Hash, F: FnMut(&K, &V) -> C>(self, f: F) -> IndexMap<C, Self>
+    where
+        Self: Default,
+    {
+        group_entries(self, f)
+    }
+}
+
+impl<K, V, S> MapOps<K, V> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type MapOf<K2, V2> = IndexMap<K2, V2, S>;
+
+    fn try_map_values<V2, E, F>(self, mut f: F) -> Result<IndexMap<K, V2, S>, E>
+    where
+        F: FnMut(V) -> Result<V2, E>,
+        IndexMap<K, V2, S>: Default,
+    {
+        let mut out = IndexMap::default();
+        out.reserve(self.len());
+
+        for (k, v) in self {
+            out.insert(k, f(v)?);
+        }
+
+        Ok(out)
+    }
+
+    fn keys_only_in<'a>(&'a self, other: &'a Self) -> Vec<&'a K> {
+        self.keys().filter(|k| !other.contains_key(*k)).collect()
+    }
+
+    fn common_keys<'a>(&'a self, other: &'a Self) -> Vec<&'a K> {
+        self.keys().filter(|k| other.contains_key(*k)).collect()
+    }
+
+    fn replace_keys_best_effort(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, K)>,
+    ) -> Vec<(K, K, ReplaceKeyErr)> {
+        let mut failures = Vec::new();
+
+        for (old, new) in pairs {
+            let err = if !self.contains_key(&old) {
+                ReplaceKeyErr::OldKeyNotExist
+            } else if old != new && self.contains_key(&new) {
+                ReplaceKeyErr::NewKeyOccupied
+            } else {
+                self.replace_key(&old, new)
+                    .expect("this should be unreachable");
+                continue;
+            };
+
+            failures.push((old, new, err));
+        }
+
+        failures
+    }
+
+    fn zip_values<'a, V2>(
+        &'a self,
+        other: &'a IndexMap<K, V2, S>,
+    ) -> impl Iterator<Item = (&'a K, &'a V, &'a V2)>
+    where
+        K: 'a,
+        V: 'a,
+        V2: 'a,
+    {
+        self.iter()
+            .filter_map(|(k, v)| other.get(k).map(|v2| (k, v, v2)))
+    }
+
+    fn sum_values<T: Sum<V>>(&self) -> T
+    where
+        V: Clone,
+    {
+        self.values().cloned().sum()
+    }
+
+    fn mean_values(&self) -> Option<f64>
+    where
+        V: Into<f64> + Clone,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let sum: f64 = self.values().cloned().map(Into::into).sum();
+        Some(sum / self.len() as f64)
+    }
+
+    fn max_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord,
+    {
+        self.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+    }
+
+    fn min_by_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+        V: Ord,
+    {
+        self.iter()
+            .min_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+    }
+
+    fn apply_patch(&mut self, patch: Self::MapOf<K, Option<V>>) {
+        for (k, v) in patch {
+            match v {
+                Some(v) => {
+                    self.insert(k, v);
+                }
+                None => {
+                    self.shift_remove(&k);
+                }
+            }
+        }
+    }
+
+    fn truncate_take(&mut self, len: usize) -> Vec<(K, V)> {
+        if len >= self.len() {
+            return Vec::new();
+        }
+
+        self.drain(len..).collect()
+    }
+
+    fn fold_values<B, F: FnMut(B, &V) -> B>(&self, init: B, f: F) -> B {
+        self.values().fold(init, f)
+    }
+
+    fn modify_or_insert<F: FnOnce(&mut V), D: FnOnce() -> V>(
+        &mut self,
+        key: K,
+        modify: F,
+        default: D,
+    ) {
+        self.entry(key).and_modify(modify).or_insert_with(default);
+    }
+
+    fn rename_prefix(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize, ReplaceKeyErr>
+    where
+        K: AsRef<str> + From<String>,
+    {
+        let rename = |k: &K| {
+            k.as_ref()
+                .strip_prefix(old_prefix)
+                .map(|rest| format!("{new_prefix}{rest}"))
+        };
+        let mut count = 0;
+
+        for k in self.keys() {
+            if let Some(new) = rename(k) {
+                if !new.starts_with(old_prefix) && self.contains_key(&K::from(new)) {
+                    return Err(ReplaceKeyErr::NewKeyOccupied);
+                }
+
+                count += 1;
+            }
+        }
+
+        if count != 0 {
+            let entries: Vec<_> = self.drain(..).collect();
+
+            self.extend(entries.into_iter().map(|(k, v)| match rename(&k) {
+                Some(new) => (K::from(new), v),
+                None => (k, v),
+            }));
+        }
+
+        Ok(count)
+    }
+
+    fn for_each_mut_sorted<F: FnMut(&K, &mut V)>(&mut self, mut f: F)
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<_> = self.iter_mut().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter().for_each(|(k, v)| f(k, v));
+    }
+
+    #[cfg(feature = "json")]
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        entries_to_json(self.iter())
+    }
+
+    fn with_capacity_like(other: &Self) -> Self
+    where
+        Self: Default,
+    {
+        let mut map = Self::default();
+        map.reserve(other.len());
+        map
+    }
+
+    fn dedup_values(&mut self) -> Vec<(K, V)>
+    where
+        V: Eq + Hash,
+    {
+        let entries = self.drain(..).collect();
+        dedup_entries(entries, self)
+    }
+
+    fn sorted_iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: Ord + 'a,
+        V: 'a,
+    {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter()
+    }
+
+    fn shrink(&mut self) {
+        self.shrink_to_fit();
+    }
+
+    fn intersect_with<V2, F: FnMut(&K, &mut V, &V2)>(
+        &mut self,
+        other: &Self::MapOf<K, V2>,
+        mut f: F,
+    ) {
+        self.retain(|k, v| other.get(k).map(|v2| f(k, v, v2)).is_some());
+    }
+
+    fn reserve_for(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+
+    fn is_subset_of(&self, other: &Self) -> bool
+    where
+        V: PartialEq,
+    {
+        self.len() <= other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+
+    fn fold_grouped<C, B, FK, FD, FF>(&self, key_fn: FK, init: FD, fold: FF) -> IndexMap<C, B>
+    where
+        C: Eq + Hash,
+        FK: FnMut(&K, &V) -> C,
+        FD: Fn() -> B,
+        FF: FnMut(&mut B, &K, &V),
+    {
+        fold_entries_grouped(self.iter(), key_fn, init, fold)
+    }
+
+    fn find_map_value<R, F: FnMut(&K, &V) -> Option<R>>(&self, mut f: F) -> Option<R> {
+        self.iter().find_map(|(k, v)| f(k, v))
+    }
+
+    fn normalize_keys<F: FnMut(&K) -> K, R: FnMut(V, V) -> V>(
+        mut self,
+        mut normalize: F,
+        mut resolve: R,
+    ) -> Self {
+        let entries: Vec<_> = self.drain(..).collect();
+
+        for (k, v) in entries {
+            let k = normalize(&k);
+
+            match self.get_index_of(&k) {
+                // Like in `replace_key`, swap the combined entry back into its first position.
+                Some(i) => {
+                    let (k, prev) = self
+                        .swap_remove_index(i)
+                        .expect("this should be unreachable");
+                    let (j, _) = self.insert_full(k, resolve(prev, v));
+                    self.swap_indices(i, j);
+                }
+                None => {
+                    self.insert(k, v);
+                }
+            }
+        }
+
+        self
+    }
+
+    fn entry_or_try_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<&mut V, E> {
+        Ok(match self.entry(key) {
+            indexmap::map::Entry::Occupied(entry) => entry.into_mut(),
+            indexmap::map::Entry::Vacant(entry) => entry.insert(f()?),
+        })
+    }
+
+    fn into_keys_values(self) -> (Vec<K>, Vec<V>) {
+        self.into_iter().unzip()
+    }
+
+    fn insert_if_absent(&mut self, key: K, value: V) -> bool {
+        match self.entry(key) {
+            indexmap::map::Entry::Occupied(_) => false,
+            indexmap::map::Entry::Vacant(entry) => {
+                entry.insert(value);
+                true
+            }
+        }
+    }
+
+    fn into_grouped<C: Eq + Hash, F: FnMut(&K, &V) -> C>(self, f: F) -> IndexMap<C, Self>
+    where
+        Self: Default,
+    {
+        group_entries(self, f)
+    }
+}
+
+/// Some extensions to `Maps` that look up values by a borrowed key `Q`.
+///
+/// Like [`MapExt`], this is generic over `Q`, so each map keeps its natural lookup bounds:
+/// `Hash + Eq` for `HashMap`, `Ord` for `BTreeMap`, and `Hash + Equivalent<K>` for `IndexMap`.
+pub trait MapLookup<K, V, Q: ?Sized = K> {
+    /// Look up several keys at once, returning the found entries in query order.
+    ///
+    /// Missing keys are skipped; use [`bulk_get_all`](Self::bulk_get_all) to see them.
+    fn bulk_get<'a>(&'a self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(&'a Q, &'a V)>
+    where
+        Q: 'a;
+
+    /// Look up several keys at once, returning one `Option` per key in query order.
+    fn bulk_get_all<'a>(
+        &'a self,
+        keys: impl IntoIterator<Item = &'a Q>,
+    ) -> Vec<(&'a Q, Option<&'a V>)>
+    where
+        Q: 'a;
+
+    /// Like [`MapExt::replace_key`], but if `k1` does not exist, insert `k2` with `default`
+    /// instead.
+    ///
+    /// The only failure is `Err(ReplaceKeyErr::NewKeyOccupied)`, when `k2` already exists
+    /// (and differs from `k1`). Then the map is left untouched.
+    fn replace_key_or_insert(&mut self, k1: &Q, k2: K, default: V) -> Result<(), ReplaceKeyErr>;
+
+    /// Get the stored key together with the value of `k`, like `get_key_value`.
+    ///
+    /// This is useful with `Borrow` lookups, when the owned key is needed.
+    fn get_key_value_q<'a>(&'a self, k: &Q) -> Option<(&'a K, &'a V)>;
+
+    /// Remove the entry of `k`, returning the stored key together with the value.
+    ///
+    /// For `IndexMap`, this uses
+    /// [`shift_remove_entry`](https://docs.rs/indexmap/latest/indexmap/map/struct.IndexMap.html#method.shift_remove_entry),
+    /// which preserves the order of the remaining entries (but is O(n)).
+    fn take_entry(&mut self, k: &Q) -> Option<(K, V)>;
+
+    /// Keep only the entries whose keys are in `keys`, returning the removed ones.
+    ///
+    /// The order of `IndexMap` is preserved, both for the kept and the removed entries.
+    fn retain_keys_in<'a>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(K, V)>
+    where
+        Q: 'a;
+}
+
+impl<K, Q, V, S> MapLookup<K, V, Q> for HashMap<K, V, S>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+{
+    fn bulk_get<'a>(&'a self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(&'a Q, &'a V)>
+    where
+        Q: 'a,
+    {
+        let get = |k| self.get(k).map(|v| (k, v));
+        keys.into_iter().filter_map(get).collect()
+    }
+
+    fn bulk_get_all<'a>(
+        &'a self,
+        keys: impl IntoIterator<Item = &'a Q>,
+    ) -> Vec<(&'a Q, Option<&'a V>)>
+    where
+        Q: 'a,
+    {
+        keys.into_iter().map(|k| (k, self.get(k))).collect()
+    }
+
+    fn replace_key_or_insert(&mut self, k1: &Q, k2: K, default: V) -> Result<(), ReplaceKeyErr> {
+        if self.contains_key(k1) {
+            return self.replace_key(k1, k2);
+        }
+
+        if self.contains_key(k2.borrow()) {
+            return Err(ReplaceKeyErr::NewKeyOccupied);
+        }
+
+        self.insert(k2, default);
+        Ok(())
+    }
+
+    fn get_key_value_q<'a>(&'a self, k: &Q) -> Option<(&'a K, &'a V)> {
+        self.get_key_value(k)
+    }
+
+    fn take_entry(&mut self, k: &Q) -> Option<(K, V)> {
+        self.remove_entry(k)
+    }
+
+    fn retain_keys_in<'a>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(K, V)>
+    where
+        Q: 'a,
+    {
+        let keys: HashSet<_> = keys.into_iter().collect();
+        let entries: Vec<_> = self.drain().collect();
+        let mut removed = Vec::new();
+
+        for (k, v) in entries {
+            if keys.contains(k.borrow()) {
+                self.insert(k, v);
+            } else {
+                removed.push((k, v));
+            }
+        }
+
+        removed
+    }
+}
+
+impl<K, Q, V> MapLookup<K, V, Q> for BTreeMap<K, V>
+where
+    K: Borrow<Q> + Ord,
+    Q: Ord + ?Sized,
+{
+    fn bulk_get<'a>(&'a self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(&'a Q, &'a V)>
+    where
+        Q: 'a,
+    {
+        let get = |k| self.get(k).map(|v| (k, v));
+        keys.into_iter().filter_map(get).collect()
+    }
+
+    fn bulk_get_all<'a>(
+        &'a self,
+        keys: impl IntoIterator<Item = &'a Q>,
+    ) -> Vec<(&'a Q, Option<&'a V>)>
+    where
+        Q: 'a,
+    {
+        keys.into_iter().map(|k| (k, self.get(k))).collect()
+    }
+
+    fn replace_key_or_insert(&mut self, k1: &Q, k2: K, default: V) -> Result<(), ReplaceKeyErr> {
+        if self.contains_key(k1) {
+            return self.replace_key(k1, k2);
+        }
+
+        if self.contains_key(k2.borrow()) {
+            return Err(ReplaceKeyErr::NewKeyOccupied);
+        }
+
+        self.insert(k2, default);
+        Ok(())
+    }
+
+    fn get_key_value_q<'a>(&'a self, k: &Q) -> Option<(&'a K, &'a V)> {
+        self.get_key_value(k)
+    }
+
+    fn take_entry(&mut self, k: &Q) -> Option<(K, V)> {
+        self.remove_entry(k)
+    }
+
+    fn retain_keys_in<'a>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(K, V)>
+    where
+        Q: 'a,
+    {
+        let keys: BTreeSet<_> = keys.into_iter().collect();
+        let mut removed = Vec::new();
+
+        for (k, v) in std::mem::take(self) {
+            if keys.contains(k.borrow()) {
+                self.insert(k, v);
+            } else {
+                removed.push((k, v));
+            }
+        }
+
+        removed
+    }
+}
+
+impl<K, Q, V, S> MapLookup<K, V, Q> for IndexMap<K, V, S>
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: ?Sized + Hash + Equivalent<K>,
+    S: BuildHasher,
+{
+    fn bulk_get<'a>(&'a self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(&'a Q, &'a V)>
+    where
+        Q: 'a,
+    {
+        let get = |k| self.get(k).map(|v| (k, v));
+        keys.into_iter().filter_map(get).collect()
+    }
+
+    fn bulk_get_all<'a>(
+        &'a self,
+        keys: impl IntoIterator<Item = &'a Q>,
+    ) -> Vec<(&'a Q, Option<&'a V>)>
+    where
+        Q: 'a,
+    {
+        keys.into_iter().map(|k| (k, self.get(k))).collect()
+    }
+
+    fn replace_key_or_insert(&mut self, k1: &Q, k2: K, default: V) -> Result<(), ReplaceKeyErr> {
+        if self.contains_key(k1) {
+            return self.replace_key(k1, k2);
+        }
+
+        if self.contains_key(k2.borrow()) {
+            return Err(ReplaceKeyErr::NewKeyOccupied);
+        }
+
+        self.insert(k2, default);
+        Ok(())
+    }
+
+    fn get_key_value_q<'a>(&'a self, k: &Q) -> Option<(&'a K, &'a V)> {
+        self.get_key_value(k)
+    }
+
+    fn take_entry(&mut self, k: &Q) -> Option<(K, V)> {
+        self.shift_remove_entry(k)
+    }
+
+    fn retain_keys_in<'a>(&mut self, keys: impl IntoIterator<Item = &'a Q>) -> Vec<(K, V)>
+    where
+        Q: 'a,
+    {
+        let mut keep = vec![false; self.len()];
+        keys.into_iter()
+            .filter_map(|k| self.get_index_of(k))
+            .for_each(|i| keep[i] = true);
+
+        let entries: Vec<_> = self.drain(..).collect();
+        let mut removed = Vec::new();
+
+        for ((k, v), keep) in entries.into_iter().zip(keep) {
+            if keep {
+                self.insert(k, v);
+            } else {
+                removed.push((k, v));
+            }
+        }
+
+        removed
+    }
+}
+
+/// Put the entries whose value appears for the first time back into `map`, and return the rest.
+fn dedup_entries<K, V, M>(entries: Vec<(K, V)>, map: &mut M) -> Vec<(K, V)>
+where
+    V: Eq + Hash,
+    M: Extend<(K, V)>,
+{
+    let mut seen = HashSet::new();
+    let duplicate: Vec<_> = entries.iter().map(|(_, v)| !seen.insert(v)).collect();
+    let mut removed = Vec::new();
+
+    map.extend(entries.into_iter().zip(duplicate).filter_map(
+        |(entry, duplicate)| match duplicate {
+            true => {
+                removed.push(entry);
+                None
+            }
+            false => Some(entry),
+        },
+    ));
+
+    removed
+}
+
+/// Fold `entries` by category, for `MapOps::fold_grouped`.
+fn fold_entries_grouped<'a, K: 'a, V: 'a, C, B, FK, FD, FF>(
+    entries: impl Iterator<Item = (&'a K, &'a V)>,
+    mut key_fn: FK,
+    init: FD,
+    mut fold: FF,
+) -> IndexMap<C, B>
+where
+    C: Eq + Hash,
+    FK: FnMut(&K, &V) -> C,
+    FD: Fn() -> B,
+    FF: FnMut(&mut B, &K, &V),
+{
+    let mut groups = IndexMap::new();
+
+    for (k, v) in entries {
+        fold(groups.entry(key_fn(k, v)).or_insert_with(&init), k, v);
+    }
+
+    groups
+}
+
+/// Move `entries` into sub-maps by category, for `MapOps::into_grouped`.
+fn group_entries<K, V, C, M, F>(entries: M, mut f: F) -> IndexMap<C, M>
+where
+    C: Eq + Hash,
+    M: IntoIterator<Item = (K, V)> + Default + Extend<(K, V)>,
+    F: FnMut(&K, &V) -> C,
+{
+    let mut groups = IndexMap::new();
+
+    for (k, v) in entries {
+        groups
+            .entry(f(&k, &v))
+            .or_insert_with(M::default)
+            .extend([(k, v)]);
+    }
+
+    groups
+}
+
+/// Error returned by `IndexMapExt::reorder`.
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ReorderErr {
+    #[error("reorder: the new order does not have the same length as the map")]
+    /// The new order does not have the same length as the map.
+    LenMismatch,
+    #[error("reorder: the new order is not a permutation")]
+    /// The new order has an out of range or repeated index.
+    NotPermutation,
+}
+
+/// Some extensions specific to
+/// [`IndexMap`](https://docs.rs/indexmap/latest/indexmap/map/struct.IndexMap.html).
+pub trait IndexMapExt<K, V> {
+    /// The index of the first entry (in order) whose value equals `value`.
+    fn index_of_value(&self, value: &V) -> Option<usize>
+    where
+        V: PartialEq;
+
+    /// Get the entry for `key`, inserting `V::default()` if it doesn't exist, and return its
+    /// index together with a mutable reference to its value.
+    fn entry_index_or_default(&mut self, key: K) -> (usize, &mut V)
+    where
+        V: Default;
+
+    /// Move the entry of `key` to the front, shifting the entries before it back by one.
+    ///
+    /// Returns whether the key exists.
+    fn move_to_front<Q>(&mut self, key: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Equivalent<K>;
+
+    /// Move the entry of `key` to the back, shifting the entries after it forward by one.
+    ///
+    /// Returns whether the key exists.
+    fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Equivalent<K>;
+
+    /// Get the entry for `key`, inserting `f()` if it doesn't exist, and return its index
+    /// together with a mutable reference to its value.
+    ///
+    /// `f` is only called if the key doesn't exist.
+    fn entry_or_insert_with_index<F: FnOnce() -> V>(&mut self, key: K, f: F) -> (usize, &mut V);
+
+    /// Rearrange the entries so that position `i` holds the entry previously at
+    /// `new_order[i]`.
+    ///
+    /// `new_order` must be a permutation of `0..len`, otherwise an error is returned and the
+    /// map is left untouched.
+    fn reorder(&mut self, new_order: &[usize]) -> Result<(), ReorderErr>;
+
+    /// Remove the entry of `key` in O(1), returning the stored key together with the value.
+    ///
+    /// The last entry is moved into the removed slot, which disturbs the order. Use
+    /// [`MapLookup::take_entry`] to preserve it instead.
+    fn swap_take<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + Hash + Equivalent<K>;
+}
+
+impl<K, V, S> IndexMapExt<K, V> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn index_of_value(&self, value: &V) -> Option<usize>
+    where
+        V: PartialEq,
+    {
+        self.values().position(|v| v == value)
+    }
+
+    fn entry_index_or_default(&mut self, key: K) -> (usize, &mut V)
+    where
+        V: Default,
+    {
+        let entry = self.entry(key);
+        (entry.index(), entry.or_default())
+    }
+
+    fn move_to_front<Q>(&mut self, key: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Equivalent<K>,
+    {
+        self.get_index_of(key)
+            .map(|i| self.move_index(i, 0))
+            .is_some()
+    }
+
+    fn move_to_back<Q>(&mut self, key: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Equivalent<K>,
+    {
+        self.get_index_of(key)
+            .map(|i| self.move_index(i, self.len() - 1))
+            .is_some()
+    }
+
+    fn entry_or_insert_with_index<F: FnOnce() -> V>(&mut self, key: K, f: F) -> (usize, &mut V) {
+        let entry = self.entry(key);
+        (entry.index(), entry.or_insert_with(f))
+    }
+
+    fn reorder(&mut self, new_order: &[usize]) -> Result<(), ReorderErr> {
+        if new_order.len() != self.len() {
+            return Err(ReorderErr::LenMismatch);
+        }
+
+        let mut seen = vec![false; self.len()];
+
+        for &i in new_order {
+            match seen.get_mut(i) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return Err(ReorderErr::NotPermutation),
+            }
+        }
+
+        let mut entries: Vec<_> = self.drain(..).map(Some).collect();
+        self.extend(
+            new_order
+                .iter()
+                .map(|&i| entries[i].take().expect("this should be unreachable")),
+        );
+        Ok(())
+    }
+
+    fn swap_take<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + Hash + Equivalent<K>,
+    {
+        self.swap_remove_entry(key)
+    }
+}
+
+/// An immutable wrapper around one of the supported maps.
+///
+/// Once frozen, the map only allows shared lookups, which encodes immutability
+/// in the type system at API boundaries. Use [`into_inner`](Self::into_inner)
+/// to get the original map back.
+///
+/// It is generic over the whole map type `M`, rather than over `<K, V, S>` like the
+/// extension traits, because the maps don't share one shape: `BTreeMap` has no hasher
+/// parameter, so `FrozenMap<HashMap<K, V, S>>`, `FrozenMap<BTreeMap<K, V>>` and
+/// `FrozenMap<IndexMap<K, V, S>>` each get their own lookup methods with the natural
+/// bounds of that map.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct FrozenMap<M>(M);
+
+impl<M> FrozenMap<M> {
+    /// Unwrap the frozen map, returning the original one.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<K, V, S> From<HashMap<K, V, S>> for FrozenMap<HashMap<K, V, S>> {
+    fn from(value: HashMap<K, V, S>) -> Self {
+        Self(value)
+    }
+}
+
+impl<K, V, S> FrozenMap<HashMap<K, V, S>>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.get(k)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.0.contains_key(k)
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// An iterator visiting all the entries, in the order of the inner map.
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, K, V> {
+        self.0.iter()
+    }
+}
+
+impl<K, V> From<BTreeMap<K, V>> for FrozenMap<BTreeMap<K, V>> {
+    fn from(value: BTreeMap<K, V>) -> Self {
+        Self(value)
+    }
+}
+
+impl<K, V> FrozenMap<BTreeMap<K, V>>
+where
+    K: Ord,
+{
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.0.get(k)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.0.contains_key(k)
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// An iterator visiting all the entries, in the order of the inner map.
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, K, V> {
+        self.0.iter()
+    }
+}
+
+impl<K, V, S> From<IndexMap<K, V, S>> for FrozenMap<IndexMap<K, V, S>> {
+    fn from(value: IndexMap<K, V, S>) -> Self {
+        Self(value)
+    }
+}
+
+impl<K, V, S> FrozenMap<IndexMap<K, V, S>>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Hash + Equivalent<K>,
+    {
+        self.0.get(k)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Equivalent<K>,
+    {
+        self.0.contains_key(k)
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// An iterator visiting all the entries, in the order of the inner map.
+    pub fn iter(&self) -> indexmap::map::Iter<'_, K, V> {
+        self.0.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_key_hashmap() {
+        let mut map = HashMap::new();
+
+        map.insert("k1".to_string(), 123);
+        map.insert("k2".to_string(), 456);
+
+        assert_eq!(map["k1"], 123);
+        assert_eq!(map["k2"], 456);
+        assert_eq!(
+            map.replace_key("k3", "k2".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+        assert_eq!(
+            map.replace_key("k3", "k3".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+        assert_eq!(
+            map.replace_key("k3", "k4".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+
+        let cloned = map.clone();
+        assert_eq!(map.replace_key("k1", "k1".to_string()), Ok(()));
+        assert_eq!(map, cloned);
+
+        assert_eq!(
+            map.replace_key("k1", "k2".to_string()),
+            Err(ReplaceKeyErr::NewKeyOccupied)
+        );
+        assert_eq!(map.replace_key("k1", "k3".to_string()), Ok(()));
+        assert!(!map.contains_key("k1"));
+        assert_eq!(map["k3"], 123);
+        assert_eq!(map["k2"], 456);
+    }
+
+    #[test]
+    fn replace_key_btreemap() {
+        let mut map = BTreeMap::new();
+
+        map.insert("k1".to_string(), 123);
+        map.insert("k2".to_string(), 456);
+
+        assert_eq!(map["k1"], 123);
+        assert_eq!(map["k2"], 456);
+        assert_eq!(
+            map.replace_key("k3", "k2".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+        assert_eq!(
+            map.replace_key("k3", "k3".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+        assert_eq!(
+            map.replace_key("k3", "k4".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+
+        let cloned = map.clone();
+        assert_eq!(map.replace_key("k1", "k1".to_string()), Ok(()));
+        assert_eq!(map, cloned);
+
+        assert_eq!(
+            map.replace_key("k1", "k2".to_string()),
+            Err(ReplaceKeyErr::NewKeyOccupied)
+        );
+        assert_eq!(map.replace_key("k1", "k3".to_string()), Ok(()));
+        assert!(!map.contains_key("k1"));
+        assert_eq!(map["k3"], 123);
+        assert_eq!(map["k2"], 456);
+    }
+
+    #[test]
+    fn replace_key_indexmap() {
+        let mut map = indexmap::indexmap! {
+            "k1".to_string() => 123,
+            "k2".to_string() => 456
+        };
+
+        assert_eq!(map["k1"], 123);
+        assert_eq!(map["k2"], 456);
+        assert_eq!(map.get_index_of("k1"), Some(0));
+        assert_eq!(map.get_index(0), Some((&"k1".to_string(), &123)));
+
+        assert_eq!(
+            map.replace_key("k3", "k2".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+        assert_eq!(
+            map.replace_key("k3", "k3".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+        assert_eq!(
+            map.replace_key("k3", "k4".to_string()),
+            Err(ReplaceKeyErr::OldKeyNotExist)
+        );
+
+        let cloned = map.clone();
+        assert_eq!(map.replace_key("k1", "k1".to_string()), Ok(()));
+        assert_eq!(map, cloned);
+
+        assert_eq!(
+            map.replace_key("k1", "k2".to_string()),
+            Err(ReplaceKeyErr::NewKeyOccupied)
+        );
+
+        assert_eq!(map.replace_key("k1", "k3".to_string()), Ok(()));
+        assert!(!map.contains_key("k1"));
+        assert_eq!(map["k3"], 123);
+        assert_eq!(map["k2"], 456);
+        assert_eq!(map.get_index_of("k3"), Some(0));
+        assert_eq!(map.get_index(0), Some((&"k3".to_string(), &123)));
+    }
+
+    #[derive(Clone)]
+    struct NoDefaultHasher(std::hash::RandomState);
+
+    impl BuildHasher for NoDefaultHasher {
+        type Hasher = std::hash::DefaultHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            self.0.build_hasher()
+        }
+    }
+
+    #[test]
+    fn map_ops_without_default_hasher() {
+        let hasher = NoDefaultHasher(Default::default());
+        let mut map = HashMap::with_hasher(hasher.clone());
+        map.extend([("B", 1), ("b", 2), ("c", 3), ("d", 3)]);
+
+        assert_eq!(map.sum_values::<i32>(), 9);
+        assert_eq!(map.dedup_values().len(), 1);
+        assert_eq!(map.len(), 3);
+        let mut map = map.normalize_keys(|k| if *k == "B" { "b" } else { k }, |a, b| a + b);
+        assert_eq!(map["b"], 3);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.truncate_take(1).len(), 1);
+        assert_eq!(map.len(), 1);
+
+        let mut map = IndexMap::with_hasher(hasher);
+        map.extend([("B", 1), ("c", 3), ("b", 2)]);
+        let mut map = map.normalize_keys(|k| k.to_lowercase().leak(), |a, b| a * 10 + b);
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), ["b", "c"]);
+        assert_eq!(map["b"], 12);
+        assert_eq!(map.truncate_take(1), [("c", 3)]);
+    }
+
+    #[test]
+    fn try_map_values() {
+        let parse = |v: &str| v.parse::<i32>();
+
+        let map = HashMap::from([("k1", "1"), ("k2", "2")]);
+        assert_eq!(
+            map.try_map_values(parse),
+            Ok(HashMap::from([("k1", 1), ("k2", 2)]))
+        );
+
+        let map = BTreeMap::from([("k1", "1"), ("k2", "x"), ("k3", "3")]);
+        let mut calls = 0;
+        assert!(map
+            .try_map_values(|v| {
+                calls += 1;
+                parse(v)
+            })
+            .is_err());
+        assert_eq!(calls, 2);
+
+        let map = indexmap::indexmap! { "k2" => "2", "k1" => "1" };
+        let mapped = map.clone().try_map_values(parse).unwrap();
+        assert!(mapped.keys().eq(map.keys()));
+        assert!(mapped.values().eq([2, 1].iter()));
+
+        let map = indexmap::indexmap! { "k2" => "2", "k1" => "x", "k3" => "y" };
+        let mut calls = 0;
+        assert!(map
+            .try_map_values(|v| {
+                calls += 1;
+                parse(v)
+            })
+            .is_err());
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn keys_difference() {
+        let a = HashMap::from([("k1", 1), ("k2", 2)]);
+        let b = HashMap::from([("k3", 3)]);
+        let mut only = a.keys_only_in(&b);
+        only.sort();
+        assert_eq!(only, [&"k1", &"k2"]);
+        assert!(a.common_keys(&b).is_empty());
+
+        let a = BTreeMap::from([("k3", 3), ("k1", 1), ("k2", 2)]);
+        let b = BTreeMap::from([("k2", 2), ("k4", 4), ("k1", 1)]);
+        assert_eq!(a.keys_only_in(&b), [&"k3"]);
+        assert_eq!(a.common_keys(&b), [&"k1", &"k2"]);
+        assert_eq!(b.keys_only_in(&b), Vec::<&&str>::new());
+        assert_eq!(b.common_keys(&b), [&"k1", &"k2", &"k4"]);
+
+        let a = indexmap::indexmap! { "k3" => 3, "k1" => 1, "k2" => 2 };
+        let b = indexmap::indexmap! { "k2" => 2, "k1" => 1 };
+        assert_eq!(a.keys_only_in(&b), [&"k3"]);
+        assert_eq!(a.common_keys(&b), [&"k1", &"k2"]);
+        assert_eq!(b.common_keys(&a), [&"k2", &"k1"]);
+        assert!(a.keys_only_in(&a).is_empty());
+    }
+
+    #[test]
+    fn replace_keys_best_effort() {
+        let pairs = || {
+            [
+                ("k1", "k4"),
+                ("k5", "k6"),
+                ("k2", "k3"),
+                ("k3", "k3"),
+                ("k4", "k1"),
+            ]
+        };
+        let report = vec![
+            ("k5", "k6", ReplaceKeyErr::OldKeyNotExist),
+            ("k2", "k3", ReplaceKeyErr::NewKeyOccupied),
+        ];
+
+        let mut map = HashMap::from([("k1", 1), ("k2", 2), ("k3", 3)]);
+        assert_eq!(map.replace_keys_best_effort(pairs()), report);
+        assert_eq!(map, HashMap::from([("k1", 1), ("k2", 2), ("k3", 3)]));
+        assert_eq!(map.replace_keys_best_effort([("k1", "k4")]), vec![]);
+        assert_eq!(map, HashMap::from([("k4", 1), ("k2", 2), ("k3", 3)]));
+
+        let mut map = BTreeMap::from([("k1", 1), ("k2", 2), ("k3", 3)]);
+        assert_eq!(map.replace_keys_best_effort(pairs()), report);
+        assert_eq!(map, BTreeMap::from([("k1", 1), ("k2", 2), ("k3", 3)]));
+
+        let mut map = indexmap::indexmap! { "k1" => 1, "k2" => 2, "k3" => 3 };
+        assert_eq!(map.replace_keys_best_effort(pairs()[..3].to_vec()), report);
+        assert!(map.keys().eq(&["k4", "k2", "k3"]));
+        assert!(map.values().eq(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn split_off_key() {
+        let mut map = HashMap::from([("k1", 1), ("k2", 2)]);
+        let tail = map.split_off_key("k2");
+        assert!(map.is_empty());
+        assert_eq!(tail, HashMap::from([("k1", 1), ("k2", 2)]));
+
+        let mut map = BTreeMap::from([("k1", 1), ("k3", 3), ("k5", 5)]);
+        let tail = map.split_off_key("k3");
+        assert_eq!(map, BTreeMap::from([("k1", 1)]));
+        assert_eq!(tail, BTreeMap::from([("k3", 3), ("k5", 5)]));
+        let tail = map.split_off_key("k0");
+        assert!(map.is_empty());
+        assert_eq!(tail, BTreeMap::from([("k1", 1)]));
+
+        let mut map = indexmap::indexmap! { "k3" => 3, "k1" => 1, "k5" => 5, "k2" => 2 };
+        let tail = map.split_off_key("k1");
+        assert!(map.keys().eq(&["k3"]));
+        assert!(tail.keys().eq(&["k1", "k5", "k2"]));
+        assert!(tail.values().eq(&[1, 5, 2]));
+        assert!(map.split_off_key("k4").is_empty());
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn index_of_value() {
+        let map = indexmap::indexmap! { "k1" => 1, "k2" => 2, "k3" => 1, "k4" => 2 };
+        assert_eq!(map.index_of_value(&2), Some(1));
+        assert_eq!(map.index_of_value(&1), Some(0));
+        assert_eq!(map.index_of_value(&3), None);
+    }
+
+    #[test]
+    fn zip_values() {
+        let a = HashMap::from([("k1", 1), ("k2", 2)]);
+        let b = HashMap::from([("k2", "two"), ("k3", "three")]);
+        assert_eq!(a.zip_values(&b).collect::<Vec<_>>(), [(&"k2", &2, &"two")]);
+
+        let a = BTreeMap::from([("k3", 3), ("k1", 1), ("k2", 2)]);
+        let b = BTreeMap::from([("k3", 'c'), ("k1", 'a'), ("k4", 'd')]);
+        assert_eq!(
+            a.zip_values(&b).collect::<Vec<_>>(),
+            [(&"k1", &1, &'a'), (&"k3", &3, &'c')]
+        );
+
+        let a = indexmap::indexmap! { "k3" => 3, "k1" => 1, "k2" => 2 };
+        let b = indexmap::indexmap! { "k1" => 'a', "k4" => 'd', "k3" => 'c' };
+        assert_eq!(
+            a.zip_values(&b).collect::<Vec<_>>(),
+            [(&"k3", &3, &'c'), (&"k1", &1, &'a')]
+        );
+    }
+
+    #[test]
+    fn frozen_map() {
+        let map = HashMap::from([("k1".to_string(), 1), ("k2".to_string(), 2)]);
+        let frozen = FrozenMap::from(map.clone());
+        assert_eq!(frozen.get("k1"), Some(&1));
+        assert!(frozen.contains_key("k2"));
+        assert!(!frozen.contains_key("k3"));
+        assert_eq!(frozen.len(), 2);
+        assert_eq!(frozen.iter().count(), 2);
+        assert_eq!(frozen.into_inner(), map);
+
+        let map = BTreeMap::from([("k2".to_string(), 2), ("k1".to_string(), 1)]);
+        let frozen = FrozenMap::from(map.clone());
+        assert_eq!(frozen.get("k2"), Some(&2));
+        assert!(frozen.iter().map(|(k, _)| k).eq(["k1", "k2"]));
+        assert_eq!(frozen.into_inner(), map);
+
+        let map = indexmap::indexmap! { "k2".to_string() => 2, "k1".to_string() => 1 };
+        let frozen = FrozenMap::from(map.clone());
+        assert_eq!(frozen.get("k1"), Some(&1));
+        assert!(!frozen.is_empty());
+        assert!(frozen.iter().map(|(k, _)| k).eq(["k2", "k1"]));
+        assert_eq!(frozen.into_inner(), map);
+    }
+
+    #[test]
+    fn sum_and_mean_values() {
+        let map = HashMap::from([("k1", 1), ("k2", 2), ("k3", 6)]);
+        assert_eq!(map.sum_values::<i32>(), 9);
+        assert_eq!(map.mean_values(), Some(3.0));
+
+        let map = BTreeMap::from([("k1", 1.5), ("k2", 2.5)]);
+        assert_eq!(map.sum_values::<f64>(), 4.0);
+        assert_eq!(map.mean_values(), Some(2.0));
+
+        let map = IndexMap::<&str, u8>::new();
+        assert_eq!(map.sum_values::<u8>(), 0);
+        assert_eq!(map.mean_values(), None);
+    }
+
+    #[test]
+    fn max_and_min_by_value() {
+        let map = HashMap::from([("k3", 5), ("k1", 5), ("k2", 1), ("k4", 1), ("k5", 3)]);
+        assert_eq!(map.max_by_value(), Some((&"k1", &5)));
+        assert_eq!(map.min_by_value(), Some((&"k2", &1)));
+
+        let map = BTreeMap::from([("k2", 7), ("k1", 7)]);
+        assert_eq!(map.max_by_value(), Some((&"k1", &7)));
+        assert_eq!(map.min_by_value(), Some((&"k1", &7)));
+
+        let map = indexmap::indexmap! { "k3" => 0, "k2" => 9, "k1" => 0 };
+        assert_eq!(map.max_by_value(), Some((&"k2", &9)));
+        assert_eq!(map.min_by_value(), Some((&"k1", &0)));
+
+        let map = IndexMap::<&str, i32>::new();
+        assert_eq!(map.max_by_value(), None);
+        assert_eq!(map.min_by_value(), None);
+    }
+
+    #[test]
+    fn apply_patch() {
+        let mut map = HashMap::from([("k1", 1), ("k2", 2)]);
+        map.apply_patch(HashMap::from([
+            ("k1", Some(10)),
+            ("k2", None),
+            ("k3", Some(3)),
+        ]));
+        assert_eq!(map, HashMap::from([("k1", 10), ("k3", 3)]));
+
+        let mut map = BTreeMap::from([("k1", 1), ("k2", 2)]);
+        map.apply_patch(BTreeMap::from([("k2", None), ("k4", None)]));
+        assert_eq!(map, BTreeMap::from([("k1", 1)]));
+
+        let mut map = indexmap::indexmap! { "k1" => 1, "k2" => 2, "k3" => 3 };
+        map.apply_patch(indexmap::indexmap! {
+            "k4" => Some(4),
+            "k1" => None,
+            "k3" => Some(30),
+        });
+        assert!(map.keys().eq(&["k2", "k3", "k4"]));
+        assert!(map.values().eq(&[2, 30, 4]));
+    }
+
+    #[test]
+    fn entry_index_or_default() {
+        let mut map = indexmap::indexmap! { "k1" => 1 };
+
+        let (i, v) = map.entry_index_or_default("k2");
+        assert_eq!((i, *v), (1, 0));
+        *v = 2;
+
+        assert_eq!(map.entry_index_or_default("k1"), (0, &mut 1));
+        assert_eq!(map.entry_index_or_default("k2"), (1, &mut 2));
+        assert_eq!(map.entry_index_or_default("k3"), (2, &mut 0));
+        assert_eq!(map.entry_index_or_default("k2"), (1, &mut 2));
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn truncate_take() {
+        let mut map = HashMap::from([("k1", 1), ("k2", 2), ("k3", 3)]);
+        let cloned = map.clone();
+        let removed = map.truncate_take(1);
+        assert_eq!(map.len(), 1);
+        assert_eq!(removed.len(), 2);
+        assert!(removed
+            .iter()
+            .all(|(k, v)| cloned[k] == *v && !map.contains_key(k)));
+        assert!(map.truncate_take(5).is_empty());
+        assert_eq!(map.len(), 1);
+
+        let mut map = BTreeMap::from([("k3", 3), ("k1", 1), ("k2", 2)]);
+        assert_eq!(map.truncate_take(1), [("k2", 2), ("k3", 3)]);
+        assert_eq!(map, BTreeMap::from([("k1", 1)]));
+        assert!(map.truncate_take(1).is_empty());
+
+        let mut map = indexmap::indexmap! { "k3" => 3, "k1" => 1, "k2" => 2 };
+        assert!(map.truncate_take(3).is_empty());
+        assert_eq!(map.truncate_take(1), [("k1", 1), ("k2", 2)]);
+        assert!(map.keys().eq(&["k3"]));
+        assert_eq!(map.truncate_take(0), [("k3", 3)]);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn fold_values() {
+        let map = HashMap::from([("k1", 1), ("k2", 2)]);
+        assert_eq!(map.fold_values(0, |acc, v| acc + v), 3);
+
+        let map = BTreeMap::from([("k2", "b"), ("k1", "a"), ("k3", "c")]);
+        assert_eq!(map.fold_values(String::new(), |acc, v| acc + v), "abc");
+
+        let map = indexmap::indexmap! { "k2" => "b", "k1" => "a", "k3" => "c" };
+        assert_eq!(map.fold_values(String::new(), |acc, v| acc + v), "bac");
+    }
+
+    #[test]
+    fn modify_or_insert() {
+        let mut map = HashMap::new();
+        map.modify_or_insert("k1", |v| *v += 1, || 10);
+        assert_eq!(map["k1"], 10);
+        map.modify_or_insert("k1", |v| *v += 1, || unreachable!());
+        assert_eq!(map["k1"], 11);
+
+        let mut map = BTreeMap::from([("k1", vec![1])]);
+        map.modify_or_insert("k1", |v| v.push(2), Vec::new);
+        map.modify_or_insert("k2", |v| v.push(2), Vec::new);
+        assert_eq!(map, BTreeMap::from([("k1", vec![1, 2]), ("k2", vec![])]));
+
+        let mut map = indexmap::indexmap! { "k2" => 2 };
+        map.modify_or_insert("k1", |_| unreachable!(), || 1);
+        map.modify_or_insert("k2", |v| *v *= 10, || unreachable!());
+        assert!(map.keys().eq(&["k2", "k1"]));
+        assert!(map.values().eq(&[20, 1]));
+    }
+
+    #[test]
+    fn rename_prefix() {
+        let s = |k: &str| k.to_string();
+
+        let mut map = HashMap::from([(s("app.a"), 1), (s("app.b"), 2), (s("other"), 3)]);
+        assert_eq!(map.rename_prefix("app.", "svc."), Ok(2));
+        assert_eq!(
+            map,
+            HashMap::from([(s("svc.a"), 1), (s("svc.b"), 2), (s("other"), 3)])
+        );
+        assert_eq!(map.rename_prefix("none.", "svc."), Ok(0));
+
+        let mut map = BTreeMap::from([(s("app.a"), 1), (s("app.b"), 2), (s("svc.b"), 3)]);
+        let cloned = map.clone();
+        assert_eq!(
+            map.rename_prefix("app.", "svc."),
+            Err(ReplaceKeyErr::NewKeyOccupied)
+        );
+        assert_eq!(map, cloned);
+
+        let mut map = BTreeMap::from([(s("ax"), 1), (s("abx"), 2)]);
+        assert_eq!(map.rename_prefix("a", "ab"), Ok(2));
+        assert_eq!(map, BTreeMap::from([(s("abx"), 1), (s("abbx"), 2)]));
+
+        let mut map = indexmap::indexmap! { s("x") => 0, s("app.b") => 2, s("app.a") => 1 };
+        assert_eq!(map.rename_prefix("app.", ""), Ok(2));
+        assert!(map.keys().eq(&["x", "b", "a"]));
+        assert!(map.values().eq(&[0, 2, 1]));
+        assert_eq!(map.rename_prefix("", "_"), Ok(3));
+        assert!(map.keys().eq(&["_x", "_b", "_a"]));
+    }
+
+    #[test]
+    fn for_each_mut_sorted() {
+        let mut map: HashMap<_, _> = (0..100).rev().map(|i| (i, 0)).collect();
+        let mut seen = Vec::new();
+        let mut n = 0;
+        map.for_each_mut_sorted(|k, v| {
+            seen.push(*k);
+            *v = n;
+            n += 1;
+        });
+        assert_eq!(seen, (0..100).collect::<Vec<_>>());
+        assert!(map.iter().all(|(k, v)| k == v));
+
+        let mut map = indexmap::indexmap! { "k2" => 0, "k3" => 0, "k1" => 0 };
+        let mut n = 0;
+        map.for_each_mut_sorted(|_, v| {
+            n += 1;
+            *v = n;
+        });
+        assert!(map.keys().eq(&["k2", "k3", "k1"]));
+        assert!(map.values().eq(&[2, 3, 1]));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn to_json() {
+        use serde_json::json;
+
+        let map = HashMap::from([("k1", 1)]);
+        assert_eq!(map.to_json().unwrap(), json!({ "k1": 1 }));
+
+        let map = BTreeMap::from([(2, "b"), (1, "a")]);
+        assert_eq!(map.to_json().unwrap(), json!([[1, "a"], [2, "b"]]));
+
+        let map = indexmap::indexmap! { "k2" => vec![2], "k1" => vec![1] };
+        let value = map.to_json().unwrap();
+        assert_eq!(value, json!({ "k2": [2], "k1": [1] }));
+        assert_eq!(value.to_string(), r#"{"k2":[2],"k1":[1]}"#);
+
+        assert_eq!(IndexMap::<i32, i32>::new().to_json().unwrap(), json!({}));
+    }
+
+    #[test]
+    fn with_capacity_like() {
+        let map: HashMap<_, _> = (0..100).map(|i| (i, i)).collect();
+        let new = HashMap::with_capacity_like(&map);
+        assert!(new.is_empty());
+        assert!(new.capacity() >= 100);
+
+        let map: BTreeMap<_, _> = (0..100).map(|i| (i, i)).collect();
+        assert!(BTreeMap::with_capacity_like(&map).is_empty());
+
+        let map: IndexMap<_, _> = (0..100).map(|i| (i, i)).collect();
+        let new = IndexMap::with_capacity_like(&map);
+        assert!(new.is_empty());
+        assert!(new.capacity() >= 100);
+    }
+
+    #[test]
+    fn dedup_values() {
+        let entries = [("a", 1), ("b", 2), ("c", 1), ("d", 3), ("e", 2), ("f", 1)];
+
+        let mut map = IndexMap::<_, _>::from(entries);
+        assert_eq!(map.dedup_values(), vec![("c", 1), ("e", 2), ("f", 1)]);
+        assert_eq!(map, IndexMap::<_, _>::from([("a", 1), ("b", 2), ("d", 3)]));
+        assert!(map.dedup_values().is_empty());
+
+        let mut map = IndexMap::<_, _>::from([("z", 1), ("y", 1)]);
+        assert_eq!(map.dedup_values(), vec![("y", 1)]);
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"z"]);
+
+        let mut map = BTreeMap::from([("z", 1), ("y", 1), ("x", 2)]);
+        assert_eq!(map.dedup_values(), vec![("z", 1)]);
+        assert_eq!(map, BTreeMap::from([("x", 2), ("y", 1)]));
+
+        let mut map = HashMap::<_, _>::from(entries);
+        let removed = map.dedup_values();
+        assert_eq!(removed.len(), 3);
+        assert_eq!(map.len(), 3);
+
+        let mut values: Vec<_> = map.values().copied().collect();
+        values.sort_unstable();
+        assert_eq!(values, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn sorted_iter() {
+        let map: HashMap<_, _> = (0..100).map(|i| (i * 37 % 101, i)).collect();
+        let first: Vec<_> = map.sorted_iter().collect();
+        let second: Vec<_> = map.sorted_iter().collect();
+
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 100);
+        assert!(first.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let map = IndexMap::<_, _>::from([("b", 1), ("c", 2), ("a", 3)]);
+        assert_eq!(
+            map.sorted_iter().collect::<Vec<_>>(),
+            vec![(&"a", &3), (&"b", &1), (&"c", &2)]
+        );
+
+        let map = BTreeMap::from([("b", 1), ("a", 2)]);
+        assert_eq!(
+            map.sorted_iter().collect::<Vec<_>>(),
+            vec![(&"a", &2), (&"b", &1)]
+        );
+    }
+
+    #[test]
+    fn map_lookup_natural_bounds() {
+        #[derive(Debug, Hash, Eq, PartialEq)]
+        struct HashOnly(u8);
+
+        #[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+        struct OrdOnly(u8);
+
+        let mut map = HashMap::from([(HashOnly(1), "a"), (HashOnly(2), "b")]);
+        assert_eq!(
+            map.bulk_get([&HashOnly(2), &HashOnly(3)]),
+            [(&HashOnly(2), &"b")]
+        );
+        assert_eq!(map.take_entry(&HashOnly(1)), Some((HashOnly(1), "a")));
+
+        let mut map = IndexMap::from([(HashOnly(1), "a"), (HashOnly(2), "b")]);
+        assert_eq!(map.retain_keys_in([&HashOnly(2)]), [(HashOnly(1), "a")]);
+
+        let mut map = BTreeMap::from([(OrdOnly(1), "a"), (OrdOnly(2), "b")]);
+        assert_eq!(map.get_key_value_q(&OrdOnly(2)), Some((&OrdOnly(2), &"b")));
+        assert_eq!(
+            map.replace_key_or_insert(&OrdOnly(3), OrdOnly(4), "c"),
+            Ok(())
+        );
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn bulk_get() {
+        let map = HashMap::<_, _>::from([("a".to_string(), 1), ("b".to_string(), 2)]);
+        assert_eq!(map.bulk_get(["b", "a"]), vec![("b", &2), ("a", &1)]);
+        assert_eq!(map.bulk_get(["c", "a", "d"]), vec![("a", &1)]);
+        assert_eq!(
+            map.bulk_get_all(["c", "a"]),
+            vec![("c", None), ("a", Some(&1))]
+        );
+
+        let map = BTreeMap::from([(1, "x"), (2, "y"), (3, "z")]);
+        assert_eq!(
+            map.bulk_get(&[3, 1, 2]),
+            vec![(&3, &"z"), (&1, &"x"), (&2, &"y")]
+        );
+        assert_eq!(map.bulk_get(&[4]), vec![]);
+
+        let map = IndexMap::<_, _>::from([("a", 1), ("b", 2)]);
+        assert_eq!(
+            map.bulk_get_all(&["b", "x", "a"]),
+            vec![(&"b", Some(&2)), (&"x", None), (&"a", Some(&1))]
+        );
+    }
+
+    #[test]
+    fn shrink() {
+        let mut map: HashMap<_, _> = (0..1000).map(|i| (i, i)).collect();
+        map.retain(|k, _| *k < 10);
+        let before = map.capacity();
+        map.shrink();
+        assert!(map.capacity() < before);
+        assert_eq!(map.len(), 10);
+
+        let mut map: IndexMap<_, _> = (0..1000).map(|i| (i, i)).collect();
+        map.truncate(10);
+        let before = map.capacity();
+        map.shrink();
+        assert!(map.capacity() < before);
+        assert_eq!(
+            map.keys().copied().collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+
+        let mut map = BTreeMap::from([(1, 2)]);
+        map.shrink();
+        assert_eq!(map, BTreeMap::from([(1, 2)]));
+    }
+
+    #[test]
+    fn move_to_front_and_back() {
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+
+        assert!(map.move_to_front("c"));
+        assert_eq!(map.get_index_of("c"), Some(0));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"c", &"a", &"b", &"d"]);
+
+        assert!(map.move_to_back("a"));
+        assert_eq!(map.get_index_of("a"), Some(3));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"c", &"b", &"d", &"a"]);
+
+        assert!(map.move_to_front("c"));
+        assert!(map.move_to_back("a"));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"c", &"b", &"d", &"a"]);
+
+        assert!(!map.move_to_front("x"));
+        assert!(!map.move_to_back("x"));
+        assert_eq!(map["d"], 4);
+    }
+
+    #[test]
+    fn entry_or_insert_with_index() {
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2)]);
+
+        let (i, v) = map.entry_or_insert_with_index("b", || unreachable!());
+        assert_eq!((i, *v), (1, 2));
+        *v = 20;
+        assert_eq!(map["b"], 20);
+
+        let (i, v) = map.entry_or_insert_with_index("c", || 3);
+        assert_eq!((i, *v), (2, 3));
+        *v += 1;
+        assert_eq!(map.get_index_of("c"), Some(2));
+        assert_eq!(map["c"], 4);
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn replace_key_or_insert() {
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2)]);
+
+        assert_eq!(map.replace_key_or_insert(&"a", "c", 0), Ok(()));
+        assert_eq!(map, IndexMap::<_, _>::from([("c", 1), ("b", 2)]));
+        assert_eq!(map.get_index_of("c"), Some(0));
+
+        assert_eq!(map.replace_key_or_insert(&"x", "d", 0), Ok(()));
+        assert_eq!(map.get_index_of("d"), Some(2));
+        assert_eq!(map["d"], 0);
+
+        assert_eq!(
+            map.replace_key_or_insert(&"c", "b", 0),
+            Err(ReplaceKeyErr::NewKeyOccupied)
+        );
+        assert_eq!(
+            map.replace_key_or_insert(&"x", "b", 0),
+            Err(ReplaceKeyErr::NewKeyOccupied)
+        );
+        assert_eq!(map.replace_key_or_insert(&"b", "b", 0), Ok(()));
+        assert_eq!(map, IndexMap::<_, _>::from([("c", 1), ("b", 2), ("d", 0)]));
+
+        let mut map = HashMap::<_, _>::from([("a".to_string(), 1)]);
+        assert_eq!(map.replace_key_or_insert("a", "b".into(), 0), Ok(()));
+        assert_eq!(map.replace_key_or_insert("a", "c".into(), 0), Ok(()));
+        assert_eq!(map, HashMap::from([("b".into(), 1), ("c".into(), 0)]));
+
+        let mut map = BTreeMap::from([(1, "x")]);
+        assert_eq!(
+            map.replace_key_or_insert(&2, 1, "y"),
+            Err(ReplaceKeyErr::NewKeyOccupied)
+        );
+        assert_eq!(map.replace_key_or_insert(&1, 2, "y"), Ok(()));
+        assert_eq!(map, BTreeMap::from([(2, "x")]));
+    }
+
+    #[test]
+    fn intersect_with() {
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+        let other = IndexMap::<_, _>::from([("d", "four"), ("x", "none"), ("b", "two")]);
+        let mut calls = Vec::new();
+
+        map.intersect_with(&other, |k, v, v2| {
+            calls.push(*k);
+            *v += v2.len();
+        });
+        assert_eq!(map, IndexMap::<_, _>::from([("b", 5), ("d", 8)]));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"b", &"d"]);
+        assert_eq!(calls, vec!["b", "d"]);
+
+        let mut map = BTreeMap::from([(1, 1.0), (2, 2.0)]);
+        map.intersect_with(&BTreeMap::from([(2, 10)]), |_, v, v2| *v *= *v2 as f64);
+        assert_eq!(map, BTreeMap::from([(2, 20.0)]));
+
+        let mut map = HashMap::<_, _>::from([("a", 1), ("b", 2)]);
+        map.intersect_with(&HashMap::<_, ()>::new(), |_, _, _| unreachable!());
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn take() {
+        let mut map: HashMap<_, _> = (0..100).map(|i| (i, i)).collect();
+        let capacity = map.capacity();
+        let taken = map.take();
+        assert!(map.is_empty());
+        assert_eq!(taken.len(), 100);
+        assert_eq!(taken.capacity(), capacity);
+        assert!((0..100).all(|i| taken[&i] == i));
+
+        let mut map = IndexMap::<_, _>::from([("b", 1), ("a", 2)]);
+        assert_eq!(map.take(), IndexMap::<_, _>::from([("b", 1), ("a", 2)]));
+        assert!(map.is_empty());
+
+        let mut map = BTreeMap::from([(1, 2)]);
+        assert_eq!(map.take(), BTreeMap::from([(1, 2)]));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn get_key_value_q() {
+        let map = HashMap::<_, _>::from([("a".to_string(), 1)]);
+        let (k, v) = map.get_key_value_q("a").unwrap();
+        assert!(std::ptr::eq(k, map.keys().next().unwrap()));
+        assert_eq!((k.as_str(), *v), ("a", 1));
+        assert_eq!(map.get_key_value_q("b"), None);
+
+        let map = BTreeMap::from([("a".to_string(), 1)]);
+        let (k, _) = map.get_key_value_q("a").unwrap();
+        assert!(std::ptr::eq(k, map.keys().next().unwrap()));
+
+        let map = IndexMap::<_, _>::from([("a".to_string(), 1), ("b".to_string(), 2)]);
+        let (k, v) = map.get_key_value_q("b").unwrap();
+        assert!(std::ptr::eq(k, map.get_index(1).unwrap().0));
+        assert_eq!(*v, 2);
+        assert_eq!(map.get_key_value_q("c"), None);
+    }
+
+    #[test]
+    fn reserve_for() {
+        let mut map = HashMap::<_, _>::from([(0, 0)]);
+        map.reserve_for(100);
+        assert!(map.capacity() >= 101);
+
+        let mut map = IndexMap::<_, _>::from([(0, 0)]);
+        map.reserve_for(100);
+        assert!(map.capacity() >= 101);
+
+        let mut map = BTreeMap::from([(0, 0)]);
+        map.reserve_for(100);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn is_subset_of() {
+        let full = HashMap::<_, _>::from([("a", 1), ("b", 2), ("c", 3)]);
+        let part = HashMap::<_, _>::from([("a", 1), ("c", 3)]);
+        let differ = HashMap::<_, _>::from([("a", 1), ("c", 4)]);
+
+        assert!(full.is_subset_of(&full) && full.is_superset_of(&full));
+        assert!(part.is_subset_of(&full) && !part.is_superset_of(&full));
+        assert!(full.is_superset_of(&part) && !full.is_subset_of(&part));
+        assert!(!differ.is_subset_of(&full) && !full.is_superset_of(&differ));
+        assert!(HashMap::new().is_subset_of(&part));
+
+        let a = IndexMap::<_, _>::from([("x", 1), ("y", 2)]);
+        let b = IndexMap::<_, _>::from([("y", 2), ("x", 1)]);
+        assert!(a.is_subset_of(&b) && a.is_superset_of(&b));
+
+        let a = BTreeMap::from([(1, "a")]);
+        assert!(a.is_subset_of(&BTreeMap::from([(1, "a"), (2, "b")])));
+        assert!(!a.is_subset_of(&BTreeMap::from([(1, "b"), (2, "b")])));
+    }
+
+    #[test]
+    fn fold_grouped() {
+        let map = IndexMap::<_, _>::from([
+            ("apple", 3),
+            ("banana", 5),
+            ("avocado", 2),
+            ("blueberry", 1),
+            ("cherry", 4),
+        ]);
+        let sums = map.fold_grouped(
+            |k, _| k.chars().next().unwrap(),
+            || 0,
+            |sum, _, v| *sum += v,
+        );
+        assert_eq!(sums, IndexMap::<_, _>::from([('a', 5), ('b', 6), ('c', 4)]));
+        assert_eq!(sums.keys().collect::<Vec<_>>(), vec![&'a', &'b', &'c']);
+
+        let map = BTreeMap::from([(1, "x"), (2, "y"), (3, "z"), (4, "w")]);
+        let groups = map.fold_grouped(|k, _| k % 2 == 0, Vec::new, |acc, _, v| acc.push(*v));
+        assert_eq!(
+            groups,
+            IndexMap::<_, _>::from([(false, vec!["x", "z"]), (true, vec!["y", "w"])])
+        );
+
+        let map: HashMap<_, _> = (1..=10).map(|i| (i, i)).collect();
+        let sums = map.fold_grouped(|_, v| v % 3, || 0, |sum, _, v| *sum += v);
+        assert_eq!(sums.len(), 3);
+        assert_eq!((sums[&0], sums[&1], sums[&2]), (18, 22, 15));
+    }
+
+    #[test]
+    fn reorder() {
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+
+        assert_eq!(map.reorder(&[2, 0, 3, 1]), Ok(()));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"c", &"a", &"d", &"b"]);
+        assert_eq!(map.get_index_of("d"), Some(2));
+        assert_eq!(map["b"], 2);
+
+        assert_eq!(map.reorder(&[0, 1, 2, 3]), Ok(()));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"c", &"a", &"d", &"b"]);
+
+        assert_eq!(map.reorder(&[0, 1, 2]), Err(ReorderErr::LenMismatch));
+        assert_eq!(map.reorder(&[0, 1, 2, 3, 4]), Err(ReorderErr::LenMismatch));
+        assert_eq!(map.reorder(&[0, 1, 1, 3]), Err(ReorderErr::NotPermutation));
+        assert_eq!(map.reorder(&[0, 1, 2, 4]), Err(ReorderErr::NotPermutation));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"c", &"a", &"d", &"b"]);
+    }
+
+    #[test]
+    fn find_map_value() {
+        let map = IndexMap::<_, _>::from([("a", "1"), ("b", "x"), ("c", "3")]);
+        let mut calls = 0;
+        let parsed = map.find_map_value(|_, v| {
+            calls += 1;
+            v.parse::<u8>().ok()
+        });
+        assert_eq!((parsed, calls), (Some(1), 1));
+
+        let late = map.find_map_value(|k, v| (*v == "3").then(|| k.to_uppercase()));
+        assert_eq!(late.as_deref(), Some("C"));
+        assert_eq!(map.find_map_value(|_, v| v.strip_prefix('z')), None);
+
+        let map = BTreeMap::from([(3, "c"), (1, "a"), (2, "b")]);
+        assert_eq!(map.find_map_value(|k, v| (*k > 1).then_some(*v)), Some("b"));
+
+        let map = HashMap::<_, _>::from([(1, 10)]);
+        assert_eq!(map.find_map_value(|k, v| Some(k + v)), Some(11));
+    }
+
+    #[test]
+    fn take_entry() {
+        let mut map = HashMap::<_, _>::from([("a".to_string(), 1), ("b".to_string(), 2)]);
+        assert_eq!(map.take_entry("a"), Some(("a".to_string(), 1)));
+        assert_eq!(map.take_entry("a"), None);
+        assert_eq!(map.len(), 1);
+
+        let mut map = BTreeMap::from([(1, "x"), (2, "y")]);
+        assert_eq!(map.take_entry(&2), Some((2, "y")));
+        assert_eq!(map.take_entry(&3), None);
+        assert_eq!(map, BTreeMap::from([(1, "x")]));
+
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+        assert_eq!(map.take_entry(&"b"), Some(("b", 2)));
+        assert_eq!(map.take_entry(&"x"), None);
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"a", &"c", &"d"]);
+    }
+
+    #[test]
+    fn count_by() {
+        let map = IndexMap::<_, i32>::from([("a", 3), ("b", -1), ("c", 0), ("d", 7), ("e", -4)]);
+        let counts = map.count_by(|_, v| v.signum());
+        assert_eq!(counts, IndexMap::<_, _>::from([(1, 2), (-1, 2), (0, 1)]));
+        assert_eq!(counts.keys().collect::<Vec<_>>(), vec![&1, &-1, &0]);
+
+        let map: HashMap<_, _> = (0..10).map(|i| (i, i * i)).collect();
+        let counts = map.count_by(|_, v| v % 2 == 0);
+        assert_eq!((counts[&true], counts[&false]), (5, 5));
+
+        assert!(BTreeMap::<u8, u8>::new().count_by(|_, _| ()).is_empty());
+    }
+
+    #[test]
+    fn normalize_keys() {
+        let lower = |k: &String| k.to_lowercase();
+
+        let map = IndexMap::<_, _>::from([
+            ("b".to_string(), 1),
+            ("A".to_string(), 2),
+            ("B".to_string(), 3),
+            ("a".to_string(), 4),
+            ("c".to_string(), 5),
+        ]);
+        let map = map.normalize_keys(lower, |a, b| a * 10 + b);
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            vec![("b".into(), 13), ("a".into(), 24), ("c".into(), 5)]
+        );
+
+        let map = HashMap::<_, _>::from([("X".to_string(), 1), ("x".to_string(), 2)]);
+        assert_eq!(
+            map.normalize_keys(lower, |a, b| a + b),
+            HashMap::from([("x".into(), 3)])
+        );
+
+        let map = BTreeMap::from([("Key".to_string(), "1"), ("KEY".to_string(), "2")]);
+        let map = map.normalize_keys(lower, |a, _| a);
+        assert_eq!(map, BTreeMap::from([("key".into(), "2")]));
+    }
+
+    #[test]
+    fn entry_or_try_insert_with() {
+        let mut map = HashMap::<_, _>::from([("a", 1)]);
+        *map.entry_or_try_insert_with("a", || -> Result<_, ()> { unreachable!() })
+            .unwrap() += 10;
+        assert_eq!(map["a"], 11);
+        assert_eq!(
+            map.entry_or_try_insert_with("b", || Ok::<_, ()>(2)),
+            Ok(&mut 2)
+        );
+        assert_eq!(
+            map.entry_or_try_insert_with("c", || Err("failed")),
+            Err("failed")
+        );
+        assert_eq!(map, HashMap::from([("a", 11), ("b", 2)]));
+
+        let mut map = BTreeMap::new();
+        assert_eq!(
+            map.entry_or_try_insert_with(1, || "x".parse::<u8>())
+                .map(|v| *v)
+                .ok(),
+            None
+        );
+        assert_eq!(
+            map.entry_or_try_insert_with(1, || "7".parse::<u8>()),
+            Ok(&mut 7)
+        );
+        assert_eq!(map, BTreeMap::from([(1, 7)]));
+
+        let mut map = IndexMap::<_, _>::from([("a", 1)]);
+        assert_eq!(map.entry_or_try_insert_with("z", || Err(())), Err(()));
+        assert_eq!(
+            map.entry_or_try_insert_with("b", || Ok::<_, ()>(2)),
+            Ok(&mut 2)
+        );
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"a", &"b"]);
+    }
+
+    #[test]
+    fn into_keys_values() {
+        let map = IndexMap::<_, _>::from([("b", 2), ("a", 1), ("c", 3)]);
+        assert_eq!(map.into_keys_values(), (vec!["b", "a", "c"], vec![2, 1, 3]));
+
+        let map = BTreeMap::from([("b", 2), ("a", 1), ("c", 3)]);
+        assert_eq!(map.into_keys_values(), (vec!["a", "b", "c"], vec![1, 2, 3]));
+
+        let map: HashMap<_, _> = (0..20).map(|i| (i, i * 2)).collect();
+        let (keys, values) = map.into_keys_values();
+        assert!(keys.iter().zip(&values).all(|(k, v)| k * 2 == *v));
+        assert_eq!(keys.len(), 20);
+    }
+
+    #[test]
+    fn swap_take() {
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+
+        assert_eq!(map.swap_take("b"), Some(("b", 2)));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"a", &"d", &"c"]);
+        assert_eq!(map.get_index_of("d"), Some(1));
+
+        assert_eq!(map.swap_take("c"), Some(("c", 3)));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"a", &"d"]);
+        assert_eq!(map.swap_take("x"), None);
+    }
+
+    #[test]
+    fn retain_keys_in() {
+        let mut map = IndexMap::<_, _>::from([("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+        let removed = map.retain_keys_in(&["d", "b", "x"]);
+        assert_eq!(removed, vec![("a", 1), ("c", 3)]);
+        assert_eq!(map, IndexMap::<_, _>::from([("b", 2), ("d", 4)]));
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"b", &"d"]);
+
+        let mut map = HashMap::<_, _>::from([("a".to_string(), 1), ("b".to_string(), 2)]);
+        let removed = map.retain_keys_in(["b", "c"]);
+        assert_eq!(removed, vec![("a".to_string(), 1)]);
+        assert_eq!(map, HashMap::from([("b".to_string(), 2)]));
+
+        let mut map = BTreeMap::from([(1, "x"), (2, "y"), (3, "z")]);
+        assert_eq!(map.retain_keys_in(&[]), vec![(1, "x"), (2, "y"), (3, "z")]);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn insert_if_absent() {
+        let mut map = HashMap::<_, _>::from([("a", 1)]);
+        assert!(!map.insert_if_absent("a", 10));
+        assert!(map.insert_if_absent("b", 2));
+        assert_eq!(map, HashMap::from([("a", 1), ("b", 2)]));
+
+        let mut map = BTreeMap::from([("a", 1)]);
+        assert!(map.insert_if_absent("b", 2));
+        assert!(!map.insert_if_absent("b", 20));
+        assert_eq!(map, BTreeMap::from([("a", 1), ("b", 2)]));
+
+        let mut map = IndexMap::<_, _>::from([("b", 2)]);
+        assert!(map.insert_if_absent("a", 1));
+        assert!(!map.insert_if_absent("b", 20));
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            vec![("b", 2), ("a", 1)]
+        );
+    }
+
+    #[test]
+    fn into_grouped() {
+        let map = IndexMap::<_, _>::from([
+            ("net.port", 80),
+            ("log.level", 2),
+            ("net.host", 1),
+            ("log.file", 3),
+            ("debug", 0),
+        ]);
+        let groups = map.into_grouped(|&k, _| k.split_once('.').map(|(section, _)| section));
+
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            vec![&Some("net"), &Some("log"), &None]
+        );
+        assert_eq!(
+            groups[&Some("net")],
+            IndexMap::<_, _>::from([("net.port", 80), ("net.host", 1)])
+        );
+        assert_eq!(
+            groups[&Some("log")].keys().collect::<Vec<_>>(),
+            vec![&"log.level", &"log.file"]
+        );
+        assert_eq!(groups[&None], IndexMap::<_, _>::from([("debug", 0)]));
+
+        let map = BTreeMap::from([(3, 'c'), (1, 'a'), (4, 'd'), (2, 'b')]);
+        let groups = map.into_grouped(|k, _| k % 2 == 0);
+        assert_eq!(groups.keys().collect::<Vec<_>>(), vec![&false, &true]);
+        assert_eq!(groups[&false], BTreeMap::from([(1, 'a'), (3, 'c')]));
+        assert_eq!(groups[&true], BTreeMap::from([(2, 'b'), (4, 'd')]));
+
+        let map: HashMap<_, _> = (0..10).map(|i| (i, i * i)).collect();
+        let groups = map.into_grouped(|k, _| k % 3);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(
+            groups[&0],
+            HashMap::from([(0, 0), (3, 9), (6, 36), (9, 81)])
+        );
+        assert_eq!(groups.values().map(HashMap::len).sum::<usize>(), 10);
+        assert!(HashMap::<i32, i32>::new()
+            .into_grouped(|k, _| *k)
+            .is_empty());
     }
 }
@@ -0,0 +1,142 @@
+#[cfg(feature = "tracing")]
+use crate::AnyRes;
+
+/// Some general extensions to [`Iterator`](https://doc.rust-lang.org/stable/std/iter/trait.Iterator.html)s.
+pub trait IteratorExt: Iterator {
+    /// Split the iterator into owned chunks of `size` items.
+    ///
+    /// The last chunk may be shorter if the iterator runs out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    fn chunks_owned(self, size: usize) -> impl Iterator<Item = Vec<Self::Item>>
+    where
+        Self: Sized;
+
+    /// Yield only the `Ok` values of an iterator of [`AnyRes`], logging every `Err` with
+    /// [`tracing::warn!`](https://docs.rs/tracing/latest/tracing/macro.warn.html).
+    #[cfg(feature = "tracing")]
+    fn oks_logging<T>(self) -> impl Iterator<Item = T>
+    where
+        Self: Iterator<Item = AnyRes<T>> + Sized;
+}
+
+impl<I: Iterator> IteratorExt for I {
+    fn chunks_owned(mut self, size: usize) -> impl Iterator<Item = Vec<Self::Item>> {
+        assert!(size != 0, "chunk size must be non-zero");
+
+        std::iter::from_fn(move || {
+            let chunk: Vec<_> = self.by_ref().take(size).collect();
+            (!chunk.is_empty()).then_some(chunk)
+        })
+    }
+
+    #[cfg(feature = "tracing")]
+    fn oks_logging<T>(self) -> impl Iterator<Item = T>
+    where
+        Self: Iterator<Item = AnyRes<T>>,
+    {
+        self.filter_map(|res| match res {
+            Ok(t) => Some(t),
+            Err(err) => {
+                tracing::warn!("{err:#}");
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_owned() {
+        assert_eq!(
+            (0..6).chunks_owned(2).collect::<Vec<_>>(),
+            [vec![0, 1], vec![2, 3], vec![4, 5]]
+        );
+        assert_eq!(
+            (0..7).chunks_owned(3).collect::<Vec<_>>(),
+            [vec![0, 1, 2], vec![3, 4, 5], vec![6]]
+        );
+        assert_eq!((0..0).chunks_owned(3).count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk size must be non-zero")]
+    fn chunks_owned_zero() {
+        let _ = (0..3).chunks_owned(0);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn oks_logging() {
+        use anyhow::{anyhow, Context};
+        use std::sync::{Arc, Mutex};
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Level, Metadata, Subscriber,
+        };
+
+        struct Capture(Arc<Mutex<Vec<(Level, String)>>>);
+        struct Message(String);
+
+        impl Visit for Message {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{value:?}");
+                }
+            }
+        }
+
+        impl Subscriber for Capture {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let mut message = Message(String::new());
+                event.record(&mut message);
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((*event.metadata().level(), message.0));
+            }
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let results = vec![
+            Ok(1),
+            Err(anyhow!("root cause")).context("bad input"),
+            Ok(2),
+            Err(anyhow!("timed out")),
+            Ok(3),
+        ];
+        let oks: Vec<_> = tracing::subscriber::with_default(Capture(logs.clone()), || {
+            results.into_iter().oks_logging().collect()
+        });
+
+        assert_eq!(oks, vec![1, 2, 3]);
+        assert_eq!(
+            *logs.lock().unwrap(),
+            vec![
+                (Level::WARN, "bad input: root cause".to_string()),
+                (Level::WARN, "timed out".to_string()),
+            ]
+        );
+    }
+}
@@ -4,6 +4,8 @@
 
 /// Extensions to the [`std::collections`](https://doc.rust-lang.org/stable/std/collections/index.html) module.
 pub mod collections;
+/// Extensions to the [`std::iter`](https://doc.rust-lang.org/stable/std/iter/index.html) module.
+pub mod iter;
 /// Extensions to the [`std::result`](https://doc.rust-lang.org/stable/std/result/index.html) module.
 pub mod result;
 /// Extensions to the [`std::task`](https://doc.rust-lang.org/stable/std/task/index.html) &
@@ -12,4 +14,14 @@ pub mod task;
 /// Extensions to the [`std::thread`](https://doc.rust-lang.org/stable/std/thread/index.html) module.
 pub mod thread;
 
-pub use result::AnyRes;
+pub use result::{AnyRes, AnyResExt};
+
+/// All the extension traits of this crate, for a single `use est::prelude::*;`.
+pub mod prelude {
+    pub use crate::{
+        collections::{IndexMapExt, MapExt, MapLookup, MapOps},
+        iter::IteratorExt,
+        result::{AnyResExt, TransposeAny, UnwrapInfallible},
+        task::{CloseAndWait, SpawnTracked},
+    };
+}
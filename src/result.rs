@@ -1,2 +1,916 @@
+use anyhow::Context;
+use std::{
+    convert::Infallible,
+    error::Error,
+    fmt::{self, Display},
+    future::Future,
+    time::Duration,
+};
+use thiserror::Error;
+
 /// `Result` with default types.
 pub type AnyRes<T = (), E = anyhow::Error> = Result<T, E>;
+
+/// Structured key-value fields attached to an error by [`AnyResExt::context_fields`].
+///
+/// They can be recovered with `err.downcast_ref::<Fields>()`, even after more context
+/// has been added on top.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Fields(pub Vec<(&'static str, String)>);
+
+impl Display for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+
+            write!(f, "{key}={value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A breadcrumb trail attached to an error by [`AnyResExt::crumb`], innermost first.
+///
+/// Unlike layers of context, all the crumbs live in a single link of the chain, and can be
+/// recovered as a list with `err.downcast_ref::<Breadcrumbs>()`. It displays as `a > b > c`.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct Breadcrumbs(pub Vec<String>);
+
+impl Display for Breadcrumbs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join(" > "))
+    }
+}
+
+/// Marker error replacing the original one by [`AnyResExt::cancel_if`].
+///
+/// Downstream code can check for it with `err.downcast_ref::<Cancelled>()`.
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[error("the operation was cancelled")]
+pub struct Cancelled;
+
+#[derive(Debug)]
+struct Reworded {
+    msg: String,
+    source: anyhow::Error,
+}
+
+impl Display for Reworded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl Error for Reworded {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Swap an [`AnyRes`] and an `Option`, without spelling out the error type.
+///
+/// Implemented both for `AnyRes<Option<T>>` (giving `Option<AnyRes<T>>`) and the other
+/// way around.
+pub trait TransposeAny {
+    /// The transposed type.
+    type Output;
+
+    /// Swap the `Result` and the `Option`, like
+    /// [`Result::transpose`](https://doc.rust-lang.org/stable/std/result/enum.Result.html#method.transpose)
+    /// and [`Option::transpose`](https://doc.rust-lang.org/stable/std/option/enum.Option.html#method.transpose).
+    fn transpose_any(self) -> Self::Output;
+}
+
+impl<T> TransposeAny for AnyRes<Option<T>> {
+    type Output = Option<AnyRes<T>>;
+
+    fn transpose_any(self) -> Option<AnyRes<T>> {
+        self.transpose()
+    }
+}
+
+impl<T> TransposeAny for Option<AnyRes<T>> {
+    type Output = AnyRes<Option<T>>;
+
+    fn transpose_any(self) -> AnyRes<Option<T>> {
+        self.transpose()
+    }
+}
+
+/// Unwrap a `Result` whose error type can never be constructed, such as
+/// [`Infallible`](https://doc.rust-lang.org/stable/std/convert/enum.Infallible.html).
+///
+/// Unlike `unwrap`, this can never panic, which is checked by the type system. It's handy
+/// for code generic over `AnyRes<T, E>` that is instantiated with an infallible `E`.
+pub trait UnwrapInfallible<T> {
+    /// Get the `Ok` value.
+    fn unwrap_infallible(self) -> T;
+}
+
+impl<T, E: Into<Infallible>> UnwrapInfallible<T> for AnyRes<T, E> {
+    fn unwrap_infallible(self) -> T {
+        self.unwrap_or_else(|err| match err.into() {})
+    }
+}
+
+#[derive(Debug)]
+struct Aggregated(Vec<anyhow::Error>);
+
+impl Display for Aggregated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.len() {
+            0 => return f.write_str("no errors"),
+            1 => f.write_str("1 error:")?,
+            n => write!(f, "{n} errors:")?,
+        }
+
+        for (i, err) in self.0.iter().enumerate() {
+            write!(f, " ({}) {err:#}", i + 1)?;
+
+            if i + 1 != self.0.len() {
+                f.write_str(";")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for Aggregated {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.first().map(AsRef::as_ref)
+    }
+}
+
+/// Combine several errors into one.
+///
+/// The combined error displays every error, numbered, like `2 errors: (1) foo; (2) bar`,
+/// and its chain continues with the first error as the source.
+///
+/// If `errors` is empty, the result is a sentinel error displaying `no errors`, without a source.
+pub fn aggregate(errors: impl IntoIterator<Item = anyhow::Error>) -> anyhow::Error {
+    Aggregated(errors.into_iter().collect()).into()
+}
+
+/// Return `Ok(())` if `cond` holds, otherwise an `Err` displaying `msg`.
+///
+/// This is a function form of
+/// [`anyhow::ensure!`](https://docs.rs/anyhow/latest/anyhow/macro.ensure.html),
+/// which composes in iterator chains.
+pub fn ensure_any(cond: bool, msg: impl Display) -> AnyRes<()> {
+    if cond {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("{msg}"))
+    }
+}
+
+/// Combine two results into a result of a pair, returning the first error, if any.
+pub fn join_any<A, B>(a: AnyRes<A>, b: AnyRes<B>) -> AnyRes<(A, B)> {
+    Ok((a?, b?))
+}
+
+/// Like [`join_any`], but for three results.
+pub fn join3<A, B, C>(a: AnyRes<A>, b: AnyRes<B>, c: AnyRes<C>) -> AnyRes<(A, B, C)> {
+    Ok((a?, b?, c?))
+}
+
+/// Like [`join_any`], but for four results.
+pub fn join4<A, B, C, D>(
+    a: AnyRes<A>,
+    b: AnyRes<B>,
+    c: AnyRes<C>,
+    d: AnyRes<D>,
+) -> AnyRes<(A, B, C, D)> {
+    Ok((a?, b?, c?, d?))
+}
+
+/// Run `f` with a timeout of `dur`, mentioning `dur` in the error either way.
+///
+/// If the timeout elapses first, the error displays `operation exceeded {dur:?}`. If `f`
+/// fails in time, `(within {dur:?})` is added as context.
+pub async fn within<T, F: Future<Output = AnyRes<T>>>(f: F, dur: Duration) -> AnyRes<T> {
+    match tokio::time::timeout(dur, f).await {
+        Ok(res) => res.with_context(|| format!("(within {dur:?})")),
+        Err(_) => Err(anyhow::anyhow!("operation exceeded {dur:?}")),
+    }
+}
+
+/// Some general extensions to [`AnyRes`].
+pub trait AnyResExt<T> {
+    /// Like [`Result::expect`](https://doc.rust-lang.org/stable/std/result/enum.Result.html#method.expect),
+    /// but the panic message also contains the whole error chain.
+    ///
+    /// # Panics
+    ///
+    /// Panics with `"{msg}: {err:#}"` if the value is an `Err`.
+    fn expect_any(self, msg: impl Display) -> T;
+
+    /// Attach structured key-value [`Fields`] to the error as context.
+    fn context_fields(self, fields: &[(&'static str, String)]) -> AnyRes<T>;
+
+    /// Replace the error with a new one displaying `msg`, keeping the original as its
+    /// [`source`](https://doc.rust-lang.org/stable/std/error/trait.Error.html#method.source).
+    ///
+    /// The alternate format (`{:#}`) still shows the whole chain. Unlike `context`, the
+    /// original error is only reachable through the chain, not by downcasting the
+    /// new error directly.
+    fn reword(self, msg: impl Display) -> AnyRes<T>;
+
+    /// Replace the error with [`Cancelled`] if `is_cancelled()` returns `true`.
+    ///
+    /// `is_cancelled` is only called on the `Err` path.
+    fn cancel_if<F: FnOnce() -> bool>(self, is_cancelled: F) -> AnyRes<T>;
+
+    /// Keep `Ok(t)` if `pred(&t)` holds, otherwise turn it into an `Err` displaying `msg`.
+    fn filter_any<F: FnOnce(&T) -> bool>(self, pred: F, msg: impl Display) -> AnyRes<T>;
+
+    /// Recover from an error of type `E`, like a typed `catch`.
+    ///
+    /// If the error can be downcast to `E`, `f` is called with it and its result is returned
+    /// as `Ok`. Any other error is kept as is.
+    fn recover<E, F>(self, f: F) -> AnyRes<T>
+    where
+        E: Error + Send + Sync + 'static,
+        F: FnOnce(E) -> T;
+
+    /// A structured JSON representation of the error, or `None` on `Ok`.
+    ///
+    /// The result looks like `{ "error": top, "chain": [top, cause, ..., root] }`.
+    #[cfg(feature = "json")]
+    fn err_json(&self) -> Option<serde_json::Value>;
+
+    /// Attach the context returned by `f`, if any, to the error.
+    ///
+    /// `f` inspects the error and is only called on the `Err` path, so only some kinds of
+    /// errors can be enriched.
+    fn context_if<F: FnOnce(&anyhow::Error) -> Option<String>>(self, f: F) -> AnyRes<T>;
+
+    /// Returns `true` if this is an `Err` that, or any source in its chain, is of type `E`.
+    ///
+    /// The result is not consumed, which is handy for branching on known error kinds.
+    fn is_err_matching<E: Error + Send + Sync + 'static>(&self) -> bool;
+
+    /// Push `msg` onto the [`Breadcrumbs`] of the error, attaching them first if needed.
+    fn crumb(self, msg: impl Into<String>) -> AnyRes<T>;
+
+    /// Transform both arms at once, like `map` followed by `map_err`.
+    fn map_both<U, FO, FE>(self, ok: FO, err: FE) -> AnyRes<U>
+    where
+        FO: FnOnce(T) -> U,
+        FE: FnOnce(anyhow::Error) -> anyhow::Error;
+
+    /// A concise one-line description of the error, or `None` on `Ok`.
+    ///
+    /// It looks like `top (caused by: root)`, or just `top` if the error has no source. This
+    /// is friendlier than the full `{:#}` chain for UIs.
+    fn short_error(&self) -> Option<String>;
+
+    /// Get the `Ok` value, for tests. More informative than `unwrap`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with `"expected Ok, got Err: {err:#}"` if the value is an `Err`.
+    fn assert_ok(self) -> T;
+
+    /// Every level of the error chain, formatted, from the top to the root cause.
+    ///
+    /// Empty on `Ok`.
+    fn err_chain_vec(&self) -> Vec<String>;
+
+    /// If this is an `Err`, call `f` again up to `attempts` times, waiting `delay` before each
+    /// retry, until it succeeds.
+    ///
+    /// Returns the first `Ok`, or the last error if every attempt fails.
+    fn or_retry<F, Fut>(
+        self,
+        attempts: usize,
+        delay: Duration,
+        f: F,
+    ) -> impl Future<Output = AnyRes<T>> + Send
+    where
+        T: Send,
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = AnyRes<T>> + Send;
+
+    /// Attach a `Debug` snapshot of `value` to the error as context, like `{label}: {value:?}`.
+    ///
+    /// The value is only formatted on the `Err` path.
+    fn context_debug<D: fmt::Debug + ?Sized>(self, label: &str, value: &D) -> AnyRes<T>;
+
+    /// If this is an `Err`, [`tokio::spawn`](https://docs.rs/tokio/latest/tokio/task/fn.spawn.html)
+    /// the future returned by `f` with the formatted error, fire-and-forget.
+    ///
+    /// Returns `self` unchanged. Must be called within a tokio runtime on the `Err` path.
+    fn on_err_spawn<F, Fut>(self, f: F) -> Self
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static;
+
+    /// Throw away the `Ok` value, keeping the error. Clearer than `.map(|_| ())`.
+    fn discard(self) -> AnyRes<()>;
+}
+
+impl<T> AnyResExt<T> for AnyRes<T> {
+    #[track_caller]
+    fn expect_any(self, msg: impl Display) -> T {
+        match self {
+            Ok(t) => t,
+            Err(err) => panic!("{msg}: {err:#}"),
+        }
+    }
+
+    fn context_fields(self, fields: &[(&'static str, String)]) -> AnyRes<T> {
+        self.map_err(|err| err.context(Fields(fields.to_vec())))
+    }
+
+    fn reword(self, msg: impl Display) -> AnyRes<T> {
+        self.map_err(|source| {
+            anyhow::Error::new(Reworded {
+                msg: msg.to_string(),
+                source,
+            })
+        })
+    }
+
+    fn cancel_if<F: FnOnce() -> bool>(self, is_cancelled: F) -> AnyRes<T> {
+        self.map_err(|err| {
+            if is_cancelled() {
+                Cancelled.into()
+            } else {
+                err
+            }
+        })
+    }
+
+    fn filter_any<F: FnOnce(&T) -> bool>(self, pred: F, msg: impl Display) -> AnyRes<T> {
+        match self {
+            Ok(t) if !pred(&t) => Err(anyhow::anyhow!("{msg}")),
+            res => res,
+        }
+    }
+
+    fn recover<E, F>(self, f: F) -> AnyRes<T>
+    where
+        E: Error + Send + Sync + 'static,
+        F: FnOnce(E) -> T,
+    {
+        self.or_else(|err| err.downcast::<E>().map(f))
+    }
+
+    #[cfg(feature = "json")]
+    fn err_json(&self) -> Option<serde_json::Value> {
+        self.as_ref().err().map(|err| {
+            serde_json::json!({
+                "error": err.to_string(),
+                "chain": err.chain().map(ToString::to_string).collect::<Vec<_>>(),
+            })
+        })
+    }
+
+    fn context_if<F: FnOnce(&anyhow::Error) -> Option<String>>(self, f: F) -> AnyRes<T> {
+        self.map_err(|err| match f(&err) {
+            Some(msg) => err.context(msg),
+            None => err,
+        })
+    }
+
+    fn is_err_matching<E: Error + Send + Sync + 'static>(&self) -> bool {
+        self.as_ref()
+            .is_err_and(|err| err.downcast_ref::<E>().is_some() || err.chain().any(|e| e.is::<E>()))
+    }
+
+    fn crumb(self, msg: impl Into<String>) -> AnyRes<T> {
+        self.map_err(|mut err| match err.downcast_mut::<Breadcrumbs>() {
+            Some(crumbs) => {
+                crumbs.0.push(msg.into());
+                err
+            }
+            None => err.context(Breadcrumbs(vec![msg.into()])),
+        })
+    }
+
+    fn map_both<U, FO, FE>(self, ok: FO, err: FE) -> AnyRes<U>
+    where
+        FO: FnOnce(T) -> U,
+        FE: FnOnce(anyhow::Error) -> anyhow::Error,
+    {
+        self.map(ok).map_err(err)
+    }
+
+    fn short_error(&self) -> Option<String> {
+        self.as_ref().err().map(|err| match err.chain().nth(1) {
+            Some(_) => format!("{err} (caused by: {})", err.root_cause()),
+            None => err.to_string(),
+        })
+    }
+
+    #[track_caller]
+    fn assert_ok(self) -> T {
+        match self {
+            Ok(t) => t,
+            Err(err) => panic!("expected Ok, got Err: {err:#}"),
+        }
+    }
+
+    fn err_chain_vec(&self) -> Vec<String> {
+        self.as_ref()
+            .err()
+            .into_iter()
+            .flat_map(|err| err.chain())
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    async fn or_retry<F, Fut>(self, attempts: usize, delay: Duration, mut f: F) -> AnyRes<T>
+    where
+        T: Send,
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = AnyRes<T>> + Send,
+    {
+        let mut res = self;
+
+        for _ in 0..attempts {
+            if res.is_ok() {
+                break;
+            }
+
+            tokio::time::sleep(delay).await;
+            res = f().await;
+        }
+
+        res
+    }
+
+    fn context_debug<D: fmt::Debug + ?Sized>(self, label: &str, value: &D) -> AnyRes<T> {
+        self.with_context(|| format!("{label}: {value:?}"))
+    }
+
+    fn on_err_spawn<F, Fut>(self, f: F) -> Self
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if let Err(err) = &self {
+            tokio::spawn(f(format!("{err:#}")));
+        }
+
+        self
+    }
+
+    fn discard(self) -> AnyRes<()> {
+        self.map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn expect_any() {
+        assert_eq!(AnyRes::Ok(5).expect_any("unreachable"), 5);
+
+        let res: AnyRes<()> = Err(anyhow!("root cause")).context("middle layer");
+        let payload = std::panic::catch_unwind(|| res.expect_any("setup failed")).unwrap_err();
+        let msg = payload.downcast_ref::<String>().unwrap();
+
+        assert!(msg.contains("setup failed"));
+        assert!(msg.contains("middle layer: root cause"));
+    }
+
+    #[test]
+    fn context_fields() {
+        assert_eq!(
+            AnyRes::Ok(5).context_fields(&[("id", "1".into())]).unwrap(),
+            5
+        );
+
+        let fields = [("id", "42".to_string()), ("user", "alice".to_string())];
+        let err = AnyRes::<()>::Err(anyhow!("not found"))
+            .context_fields(&fields)
+            .context("request failed")
+            .unwrap_err();
+
+        assert_eq!(err.downcast_ref::<Fields>(), Some(&Fields(fields.to_vec())));
+        assert_eq!(
+            format!("{err:#}"),
+            "request failed: id=42, user=alice: not found"
+        );
+    }
+
+    #[test]
+    fn reword() {
+        assert_eq!(AnyRes::Ok(5).reword("unreachable").unwrap(), 5);
+
+        let err = AnyRes::<()>::Err(anyhow!("connection refused"))
+            .context("connect failed")
+            .reword("service unavailable")
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "service unavailable");
+        assert_eq!(
+            format!("{err:#}"),
+            "service unavailable: connect failed: connection refused"
+        );
+        assert_eq!(err.chain().count(), 3);
+    }
+
+    #[test]
+    fn cancel_if() {
+        assert_eq!(AnyRes::Ok(5).cancel_if(|| unreachable!()).unwrap(), 5);
+
+        let err = AnyRes::<()>::Err(anyhow!("interrupted"))
+            .cancel_if(|| true)
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Cancelled>(), Some(&Cancelled));
+
+        let err = AnyRes::<()>::Err(anyhow!("disk full"))
+            .cancel_if(|| false)
+            .unwrap_err();
+        assert!(err.downcast_ref::<Cancelled>().is_none());
+        assert_eq!(err.to_string(), "disk full");
+    }
+
+    #[test]
+    fn filter_any() {
+        assert_eq!(
+            AnyRes::Ok(5).filter_any(|t| *t > 3, "too small").unwrap(),
+            5
+        );
+
+        let err = AnyRes::Ok(2)
+            .filter_any(|t| *t > 3, "too small")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "too small");
+
+        let err = AnyRes::<i32>::Err(anyhow!("parse failed"))
+            .filter_any(|_| unreachable!(), "too small")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "parse failed");
+    }
+
+    #[test]
+    fn aggregate() {
+        let err = super::aggregate([anyhow!("root").context("first")]);
+        assert_eq!(err.to_string(), "1 error: (1) first: root");
+        assert_eq!(err.chain().nth(1).unwrap().to_string(), "first");
+
+        let err = super::aggregate([anyhow!("first"), anyhow!("second")]);
+        assert_eq!(err.to_string(), "2 errors: (1) first; (2) second");
+        assert_eq!(err.chain().count(), 2);
+        assert_eq!(err.source().unwrap().to_string(), "first");
+
+        let err = super::aggregate([]);
+        assert_eq!(err.to_string(), "no errors");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn transpose_any() {
+        assert_eq!(AnyRes::Ok(Some(5)).transpose_any().unwrap().unwrap(), 5);
+        assert!(AnyRes::<Option<i32>>::Ok(None).transpose_any().is_none());
+
+        let err = AnyRes::<Option<i32>>::Err(anyhow!("failed"))
+            .transpose_any()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "failed");
+
+        assert_eq!(Some(AnyRes::Ok(5)).transpose_any().unwrap(), Some(5));
+        assert_eq!(None::<AnyRes<i32>>.transpose_any().unwrap(), None);
+        assert!(Some(AnyRes::<i32>::Err(anyhow!("failed")))
+            .transpose_any()
+            .is_err());
+    }
+
+    #[test]
+    fn ensure_any() {
+        assert!(super::ensure_any(true, "unreachable").is_ok());
+        assert_eq!(
+            super::ensure_any(false, "invariant broken")
+                .unwrap_err()
+                .to_string(),
+            "invariant broken"
+        );
+
+        let res: AnyRes<()> = [1, 2, -3]
+            .iter()
+            .try_for_each(|n| super::ensure_any(*n > 0, format!("{n} is not positive")));
+        assert_eq!(res.unwrap_err().to_string(), "-3 is not positive");
+    }
+
+    #[test]
+    fn recover() {
+        use std::num::ParseIntError;
+
+        let parse = |s: &str| -> AnyRes<i32> { s.parse::<i32>().context("parse failed") };
+
+        assert_eq!(parse("5").recover(|_: ParseIntError| 0).unwrap(), 5);
+        assert_eq!(parse("x").recover(|_: ParseIntError| 0).unwrap(), 0);
+
+        let err = parse("x").recover(|_: Cancelled| 0).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "parse failed: invalid digit found in string"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn err_json() {
+        use serde_json::json;
+
+        assert_eq!(AnyRes::Ok(5).err_json(), None);
+
+        let res: AnyRes<()> = Err(anyhow!("root cause"))
+            .context("middle layer")
+            .context("top level");
+        assert_eq!(
+            res.err_json(),
+            Some(json!({
+                "error": "top level",
+                "chain": ["top level", "middle layer", "root cause"],
+            }))
+        );
+    }
+
+    #[test]
+    fn unwrap_infallible() {
+        use std::str::FromStr;
+
+        fn parse_all<T: FromStr>(inputs: &[&str]) -> AnyRes<Vec<T>, T::Err> {
+            inputs.iter().map(|s| s.parse()).collect()
+        }
+
+        let res: AnyRes<u8, Infallible> = Ok(5);
+        assert_eq!(res.unwrap_infallible(), 5);
+
+        let strings: Vec<String> = parse_all(&["a", "b"]).unwrap_infallible();
+        assert_eq!(strings, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn context_if() {
+        use std::io;
+
+        let enrich = |err: &anyhow::Error| {
+            err.downcast_ref::<io::Error>()
+                .filter(|err| err.kind() == io::ErrorKind::NotFound)
+                .map(|_| "config file missing".to_string())
+        };
+
+        assert_eq!(AnyRes::Ok(5).context_if(|_| unreachable!()).unwrap(), 5);
+
+        let err = AnyRes::<()>::Err(io::Error::from(io::ErrorKind::NotFound).into())
+            .context_if(enrich)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "config file missing");
+        assert!(err.downcast_ref::<io::Error>().is_some());
+
+        let err = AnyRes::<()>::Err(io::Error::from(io::ErrorKind::PermissionDenied).into())
+            .context_if(enrich)
+            .unwrap_err();
+        assert_eq!(err.chain().count(), 1);
+
+        let err = AnyRes::<()>::Err(anyhow!("other"))
+            .context_if(enrich)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "other");
+    }
+
+    #[test]
+    fn join_any() {
+        let ok = |n: u8| AnyRes::Ok(n);
+        let err = |msg: &'static str| AnyRes::<u8>::Err(anyhow!(msg));
+
+        assert_eq!(super::join_any(ok(1), Ok("a")).unwrap(), (1, "a"));
+        assert_eq!(
+            super::join_any(err("a"), ok(2)).unwrap_err().to_string(),
+            "a"
+        );
+        assert_eq!(
+            super::join_any(ok(1), err("b")).unwrap_err().to_string(),
+            "b"
+        );
+        assert_eq!(
+            super::join_any(err("a"), err("b")).unwrap_err().to_string(),
+            "a"
+        );
+
+        assert_eq!(join3(ok(1), ok(2), ok(3)).unwrap(), (1, 2, 3));
+        assert_eq!(join3(ok(1), ok(2), err("c")).unwrap_err().to_string(), "c");
+        assert_eq!(join4(ok(1), ok(2), ok(3), ok(4)).unwrap(), (1, 2, 3, 4));
+        assert_eq!(
+            join4(ok(1), err("b"), ok(3), err("d"))
+                .unwrap_err()
+                .to_string(),
+            "b"
+        );
+    }
+
+    #[test]
+    fn is_err_matching() {
+        use std::{fmt::Error as FmtError, io};
+
+        let res = AnyRes::<()>::Err(io::Error::from(io::ErrorKind::NotFound).into());
+        assert!(res.is_err_matching::<io::Error>());
+        assert!(!res.is_err_matching::<FmtError>());
+
+        let res = res.context("load config").context("startup");
+        assert!(res.is_err_matching::<io::Error>());
+        assert!(!res.is_err_matching::<FmtError>());
+
+        let res = AnyRes::<()>::Err(anyhow!("plain")).context(Cancelled);
+        assert!(res.is_err_matching::<Cancelled>());
+
+        assert!(!AnyRes::Ok(5).is_err_matching::<io::Error>());
+    }
+
+    #[test]
+    fn crumb() {
+        assert_eq!(AnyRes::Ok(5).crumb("unreachable").unwrap(), 5);
+
+        let err = AnyRes::<()>::Err(anyhow!("file not found"))
+            .crumb("read file")
+            .crumb("load config")
+            .context("flat context")
+            .crumb("startup")
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<Breadcrumbs>().unwrap().0,
+            vec!["read file", "load config", "startup"]
+        );
+        assert_eq!(
+            format!("{err:#}"),
+            "flat context: read file > load config > startup: file not found"
+        );
+    }
+
+    #[test]
+    fn map_both() {
+        let res = AnyRes::Ok(5).map_both(|n| n * 2, |_| unreachable!());
+        assert_eq!(res.unwrap(), 10);
+
+        let err = AnyRes::<u8>::Err(anyhow!("inner"))
+            .map_both(|_| -> u16 { unreachable!() }, |err| err.context("outer"))
+            .unwrap_err();
+        assert_eq!(format!("{err:#}"), "outer: inner");
+    }
+
+    #[test]
+    fn short_error() {
+        assert_eq!(AnyRes::Ok(5).short_error(), None);
+
+        let res = AnyRes::<()>::Err(anyhow!("disk full"));
+        assert_eq!(res.short_error().unwrap(), "disk full");
+
+        let res = res.context("write cache").context("save document");
+        assert_eq!(
+            res.short_error().unwrap(),
+            "save document (caused by: disk full)"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn within() {
+        use tokio::time::sleep;
+
+        let dur = Duration::from_secs(1);
+
+        let res = super::within(async { Ok(5) }, dur).await;
+        assert_eq!(res.unwrap(), 5);
+
+        let err = super::within(async { AnyRes::<()>::Err(anyhow!("refused")) }, dur)
+            .await
+            .unwrap_err();
+        assert_eq!(format!("{err:#}"), "(within 1s): refused");
+
+        let err = super::within(
+            async {
+                sleep(Duration::from_secs(2)).await;
+                Ok(())
+            },
+            dur,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "operation exceeded 1s");
+    }
+
+    #[test]
+    fn assert_ok() {
+        assert_eq!(AnyRes::Ok(5).assert_ok(), 5);
+
+        let res: AnyRes<()> = Err(anyhow!("root cause")).context("middle layer");
+        let payload = std::panic::catch_unwind(|| res.assert_ok()).unwrap_err();
+
+        assert_eq!(
+            payload.downcast_ref::<String>().unwrap(),
+            "expected Ok, got Err: middle layer: root cause"
+        );
+    }
+
+    #[test]
+    fn err_chain_vec() {
+        assert!(AnyRes::Ok(5).err_chain_vec().is_empty());
+
+        let res = AnyRes::<()>::Err(anyhow!("root"));
+        assert_eq!(res.err_chain_vec(), vec!["root"]);
+
+        let res = res.context("middle").context("top");
+        assert_eq!(res.err_chain_vec(), vec!["top", "middle", "root"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn or_retry() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::time::Instant;
+
+        let calls = AtomicUsize::new(0);
+        let flaky = || async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                n if n < 3 => Err(anyhow!("attempt {n} failed")),
+                n => Ok(n),
+            }
+        };
+        let delay = Duration::from_millis(100);
+
+        let start = Instant::now();
+        assert_eq!(AnyRes::Ok(9).or_retry(3, delay, flaky).await.unwrap(), 9);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        let res = flaky().await.or_retry(5, delay, flaky).await;
+        assert_eq!(res.unwrap(), 3);
+        assert_eq!(start.elapsed(), delay * 3);
+
+        calls.store(0, Ordering::SeqCst);
+        let err = flaky().await.or_retry(1, delay, flaky).await.unwrap_err();
+        assert_eq!(err.to_string(), "attempt 1 failed");
+
+        let err = flaky().await.or_retry(0, delay, flaky).await.unwrap_err();
+        assert_eq!(err.to_string(), "attempt 2 failed");
+    }
+
+    #[test]
+    fn context_debug() {
+        struct Panicky;
+
+        impl fmt::Debug for Panicky {
+            fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+                unreachable!()
+            }
+        }
+
+        assert_eq!(AnyRes::Ok(5).context_debug("state", &Panicky).unwrap(), 5);
+
+        let err = AnyRes::<()>::Err(anyhow!("not found"))
+            .context_debug("request", &(7, "/"))
+            .context_debug("path", "/")
+            .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            r#"path: "/": request: (7, "/"): not found"#
+        );
+    }
+
+    #[tokio::test]
+    async fn on_err_spawn() {
+        use tokio::sync::mpsc;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let report =
+            |tx: mpsc::UnboundedSender<String>| move |err| async move { tx.send(err).unwrap() };
+
+        assert_eq!(AnyRes::Ok(1).on_err_spawn(report(tx.clone())).unwrap(), 1);
+
+        let err = Err::<(), _>(anyhow!("root cause"))
+            .context("request failed")
+            .on_err_spawn(report(tx.clone()))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "request failed");
+
+        drop(tx);
+        let mut reported = Vec::new();
+        while let Some(err) = rx.recv().await {
+            reported.push(err);
+        }
+        assert_eq!(reported, ["request failed: root cause"]);
+    }
+
+    #[test]
+    fn discard() {
+        assert!(AnyRes::Ok(vec![1, 2]).discard().is_ok());
+
+        let err = AnyRes::<u8>::Err(anyhow!("not found"))
+            .discard()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "not found");
+    }
+}
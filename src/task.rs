@@ -1,7 +1,28 @@
+use crate::AnyRes;
+use anyhow::Context;
 use derive_more::Display;
+use futures_util::{future::BoxFuture, stream::FuturesUnordered, Stream};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::num::NonZeroU64;
-use tokio_util::task::{task_tracker::TaskTrackerWaitFuture, TaskTracker};
+use std::{
+    collections::HashMap,
+    future::Future,
+    num::{IntErrorKind, NonZeroU64},
+    pin::pin,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock, RwLock, Weak},
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+use tokio::{
+    sync::{broadcast, mpsc, Notify, OwnedSemaphorePermit, Semaphore},
+    task::{JoinError, JoinHandle, JoinSet},
+    time::Instant,
+};
+use tokio_util::{
+    sync::CancellationToken,
+    task::{task_tracker::TaskTrackerWaitFuture, TaskTracker},
+};
 
 /// A [`TaskId`](https://docs.rs/tokio/latest/tokio/task/struct.Id.html) that can be `serde`.
 #[derive(Debug, Display, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash)]
@@ -14,61 +35,2138 @@ impl From<tokio::task::Id> for TaskId {
     }
 }
 
+/// Error returned when parsing a [`TaskId`] from a string.
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TaskIdParseErr {
+    #[error("parse TaskId: the input is empty")]
+    /// The input is empty.
+    Empty,
+    #[error("parse TaskId: the id is zero")]
+    /// The id is zero.
+    Zero,
+    #[error("parse TaskId: the input is not a valid decimal id")]
+    /// The input is not a valid decimal id (or is too large).
+    Invalid,
+}
+
+impl FromStr for TaskId {
+    type Err = TaskIdParseErr;
+
+    /// Parse a decimal id, as produced by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self)
+            .map_err(|err: std::num::ParseIntError| match err.kind() {
+                IntErrorKind::Empty => TaskIdParseErr::Empty,
+                IntErrorKind::Zero => TaskIdParseErr::Zero,
+                _ => TaskIdParseErr::Invalid,
+            })
+    }
+}
+
+/// A tokio [`Instant`](https://docs.rs/tokio/latest/tokio/time/struct.Instant.html) that can
+/// be `serde`.
+///
+/// An `Instant` is opaque, so it is (de)serialized as the
+/// [`SystemTime`](https://doc.rust-lang.org/stable/std/time/struct.SystemTime.html) it corresponds
+/// to at that moment. Round trips are therefore only as precise as the two clocks are steady.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SerdeInstant(pub Instant);
+
+impl SerdeInstant {
+    /// The current instant.
+    pub fn now() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl From<Instant> for SerdeInstant {
+    fn from(value: Instant) -> Self {
+        Self(value)
+    }
+}
+
+impl From<SerdeInstant> for Instant {
+    fn from(value: SerdeInstant) -> Self {
+        value.0
+    }
+}
+
+impl Serialize for SerdeInstant {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (now, system_now) = (Instant::now(), SystemTime::now());
+        let system = match now.checked_duration_since(self.0) {
+            Some(ago) => system_now.checked_sub(ago),
+            None => system_now.checked_add(self.0 - now),
+        };
+
+        system
+            .ok_or_else(|| serde::ser::Error::custom("SerdeInstant: out of the SystemTime range"))?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SerdeInstant {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let system = SystemTime::deserialize(deserializer)?;
+        let (now, system_now) = (Instant::now(), SystemTime::now());
+        let instant = match system_now.duration_since(system) {
+            Ok(ago) => now.checked_sub(ago),
+            Err(err) => now.checked_add(err.duration()),
+        };
+
+        instant
+            .map(Self)
+            .ok_or_else(|| serde::de::Error::custom("SerdeInstant: out of the Instant range"))
+    }
+}
+
 /// Execute [`close`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html#method.close)
 /// and [`wait`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html#method.wait)
 /// for [`TaskTracker`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html) at once.
 pub trait CloseAndWait {
-    fn close_and_wait(&self) -> TaskTrackerWaitFuture;
+    fn close_and_wait(&self) -> TaskTrackerWaitFuture<'_>;
+
+    /// Reopen the tracker so that it can be used for a new batch of tasks.
+    ///
+    /// Returns `true` if the tracker was closed before this call.
+    fn reopen(&self) -> bool;
+
+    /// Close the tracker, wait for the current batch of tasks to finish, and then reopen it.
+    fn close_wait_reopen(&self) -> impl Future<Output = ()> + Send + '_;
+
+    /// Close the tracker and wait for it, calling `on_tick` with the number of remaining
+    /// tasks every `interval` until all of them have finished.
+    fn wait_with_progress<F: FnMut(usize) + Send>(
+        &self,
+        interval: Duration,
+        on_tick: F,
+    ) -> impl Future<Output = ()> + Send;
 }
 
 impl CloseAndWait for TaskTracker {
-    fn close_and_wait(&self) -> TaskTrackerWaitFuture {
+    fn close_and_wait(&self) -> TaskTrackerWaitFuture<'_> {
         self.close();
         self.wait()
     }
+
+    fn reopen(&self) -> bool {
+        TaskTracker::reopen(self)
+    }
+
+    async fn close_wait_reopen(&self) {
+        self.close_and_wait().await;
+        CloseAndWait::reopen(self);
+    }
+
+    async fn wait_with_progress<F: FnMut(usize) + Send>(&self, interval: Duration, mut on_tick: F) {
+        let mut wait = pin!(self.close_and_wait());
+
+        while tokio::time::timeout(interval, &mut wait).await.is_err() {
+            on_tick(self.len());
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Spawning extensions for
+/// [`TaskTracker`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html).
+pub trait SpawnTracked {
+    /// Wait for a permit from `sem`, then spawn a tracked task holding the permit until
+    /// it finishes, returning its id.
+    ///
+    /// This bounds the number of tasks running at once to the permits of `sem`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sem` has been closed.
+    fn spawn_limited<F>(&self, sem: &Arc<Semaphore>, f: F) -> impl Future<Output = TaskId> + Send
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static;
 
-    #[tokio::test]
-    async fn from_tokio_task_id() {
-        let id = tokio::spawn(async { tokio::task::id() }).await.unwrap();
-        assert_eq!(id.to_string(), TaskId::from(id).to_string());
+    /// Spawn a tracked task that waits for `delay` before running `f`, returning its id.
+    ///
+    /// The task is tracked from the start, so closing and waiting for the tracker also
+    /// waits for the delay.
+    fn spawn_after<F>(&self, delay: Duration, f: F) -> TaskId
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static;
+}
+
+impl SpawnTracked for TaskTracker {
+    async fn spawn_limited<F>(&self, sem: &Arc<Semaphore>, f: F) -> TaskId
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let permit = sem
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore has been closed");
+
+        self.spawn(async move {
+            let _permit = permit;
+            f.await
+        })
+        .id()
+        .into()
     }
 
-    fn tracker_spawn() -> TaskTracker {
-        let tracker = TaskTracker::new();
+    fn spawn_after<F>(&self, delay: Duration, f: F) -> TaskId
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn(async move {
+            tokio::time::sleep(delay).await;
+            f.await
+        })
+        .id()
+        .into()
+    }
+}
 
-        for i in 0..3 {
-            tracker.spawn(async move { i });
+/// A [`TaskTracker`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html)
+/// whose tasks carry a human-readable name alongside their [`TaskId`].
+///
+/// The names of the running tasks can be listed with [`named_tasks`](Self::named_tasks).
+/// A task removes itself as soon as it finishes or is aborted. Cloning gives a handle to the
+/// same tracker and names.
+#[derive(Debug, Clone, Default)]
+pub struct NamedTracker {
+    tracker: TaskTracker,
+    names: Arc<Mutex<IndexMap<TaskId, String>>>,
+}
+
+/// Unregisters a task spawned by [`NamedTracker::spawn`] once its future is dropped, i.e.
+/// when it finishes or is aborted.
+///
+/// The id is only known after spawning, so it is set under the `names` lock. If the task
+/// ends before that, the guard sets it to `None` instead, and the task is never registered.
+struct NamedTaskGuard {
+    names: Arc<Mutex<IndexMap<TaskId, String>>>,
+    id: Arc<OnceLock<Option<TaskId>>>,
+}
+
+impl Drop for NamedTaskGuard {
+    fn drop(&mut self) {
+        let mut names = self.names.lock().unwrap();
+
+        if self.id.set(None).is_ok() {
+            return;
         }
 
-        tracker
+        if let Some(Some(id)) = self.id.get() {
+            names.shift_remove(id);
+        }
     }
+}
 
-    #[tokio::test]
-    async fn close_and_wait() {
-        use std::time::Duration;
-        use tokio::time::timeout;
+impl NamedTracker {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let tracker = tracker_spawn();
-        assert!(timeout(Duration::from_secs_f64(1.5), tracker.wait())
-            .await
-            .is_err());
+    /// Spawn a task with a human-readable name, returning its id.
+    pub fn spawn<F>(&self, name: impl Into<String>, f: F) -> TaskId
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let cell = Arc::new(OnceLock::new());
+        let guard = NamedTaskGuard {
+            names: self.names.clone(),
+            id: cell.clone(),
+        };
+        let id = self
+            .tracker
+            .spawn(async move {
+                let _guard = guard;
+                f.await
+            })
+            .id()
+            .into();
+        let mut names = self.names.lock().unwrap();
 
-        let tracker = tracker_spawn();
-        tracker.close();
-        assert!(timeout(Duration::from_secs_f64(1.5), tracker.wait())
+        if cell.set(Some(id)).is_ok() {
+            names.insert(id, name.into());
+        }
+
+        id
+    }
+
+    /// The running tasks and their names, in spawn order.
+    pub fn named_tasks(&self) -> IndexMap<TaskId, String> {
+        self.names.lock().unwrap().clone()
+    }
+
+    /// The number of tasks that are still running.
+    pub fn len(&self) -> usize {
+        self.tracker.len()
+    }
+
+    /// Returns `true` if there is no running task.
+    pub fn is_empty(&self) -> bool {
+        self.tracker.is_empty()
+    }
+
+    /// Like [`CloseAndWait::close_and_wait`], on the inner tracker.
+    pub fn close_and_wait(&self) -> TaskTrackerWaitFuture<'_> {
+        self.tracker.close_and_wait()
+    }
+}
+
+/// A [`TaskTracker`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html)
+/// that runs at most a fixed number of tasks at once.
+///
+/// Each task holds a permit of an internal [`Semaphore`] until it finishes. Cloning gives a
+/// handle to the same tracker and limit.
+#[derive(Debug, Clone)]
+pub struct LimitedTracker {
+    tracker: TaskTracker,
+    sem: Arc<Semaphore>,
+}
+
+impl LimitedTracker {
+    /// Create a tracker running at most `limit` tasks at once.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            tracker: TaskTracker::new(),
+            sem: Arc::new(Semaphore::new(limit)),
+        }
+    }
+
+    /// Spawn a task if the limit is not reached yet, returning its id, or `None` otherwise.
+    pub fn try_spawn<F>(&self, f: F) -> Option<TaskId>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let permit = self.sem.clone().try_acquire_owned().ok()?;
+        Some(self.spawn_with(permit, f))
+    }
+
+    /// Wait until the limit allows it, then spawn a task, returning its id.
+    pub async fn spawn<F>(&self, f: F) -> TaskId
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let permit = self
+            .sem
+            .clone()
+            .acquire_owned()
             .await
-            .is_ok());
+            .expect("this should be unreachable");
 
-        let tracker = tracker_spawn();
-        assert!(
-            timeout(Duration::from_secs_f64(1.5), tracker.close_and_wait())
-                .await
-                .is_ok()
-        );
+        self.spawn_with(permit, f)
+    }
+
+    fn spawn_with<F>(&self, permit: OwnedSemaphorePermit, f: F) -> TaskId
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tracker
+            .spawn(async move {
+                let _permit = permit;
+                f.await
+            })
+            .id()
+            .into()
+    }
+
+    /// The number of tasks that are still running.
+    pub fn len(&self) -> usize {
+        self.tracker.len()
+    }
+
+    /// Returns `true` if there is no running task.
+    pub fn is_empty(&self) -> bool {
+        self.tracker.is_empty()
+    }
+
+    /// Like [`CloseAndWait::close_and_wait`], on the inner tracker.
+    pub fn close_and_wait(&self) -> TaskTrackerWaitFuture<'_> {
+        self.tracker.close_and_wait()
+    }
+}
+
+/// A [`TaskTracker`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html)
+/// that can have child trackers, forming a supervision tree.
+///
+/// [`close_and_wait_recursive`](Self::close_and_wait_recursive) closes it and all its
+/// descendants before waiting for them. A parent only keeps weak references to its children:
+/// a child leaves the tree once every handle to it is dropped and its tasks, which hold it
+/// alive, have finished. Cloning gives a handle to the same tracker.
+#[derive(Debug, Clone, Default)]
+pub struct SupervisedTracker(Arc<SupervisedNode>);
+
+#[derive(Debug, Default)]
+struct SupervisedNode {
+    tracker: TaskTracker,
+    children: Mutex<Vec<Weak<SupervisedNode>>>,
+}
+
+impl SupervisedTracker {
+    /// Create a tracker without children.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a child tracker, which is closed and waited for by
+    /// [`close_and_wait_recursive`](Self::close_and_wait_recursive) on this one.
+    pub fn child(&self) -> Self {
+        let child = Self::new();
+        let mut children = self.0.children.lock().unwrap();
+
+        children.retain(|child| child.strong_count() > 0);
+        children.push(Arc::downgrade(&child.0));
+        child
+    }
+
+    /// Spawn a task, returning its id.
+    pub fn spawn<F>(&self, f: F) -> TaskId
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let node = self.0.clone();
+
+        self.0
+            .tracker
+            .spawn(async move {
+                let _node = node;
+                f.await
+            })
+            .id()
+            .into()
+    }
+
+    /// The number of tasks that are still running.
+    pub fn len(&self) -> usize {
+        self.0.tracker.len()
+    }
+
+    /// Returns `true` if there is no running task.
+    pub fn is_empty(&self) -> bool {
+        self.0.tracker.is_empty()
+    }
+
+    /// Returns `true` if the tracker is closed.
+    pub fn is_closed(&self) -> bool {
+        self.0.tracker.is_closed()
+    }
+
+    /// Like [`CloseAndWait::close_and_wait`], on this tracker only.
+    pub fn close_and_wait(&self) -> TaskTrackerWaitFuture<'_> {
+        self.0.tracker.close_and_wait()
+    }
+
+    /// Close this tracker and all its descendants created by [`child`](Self::child),
+    /// then wait for all of them.
+    pub async fn close_and_wait_recursive(&self) {
+        let nodes = self.subtree();
+
+        nodes.iter().for_each(|node| {
+            node.tracker.close();
+        });
+
+        for node in &nodes {
+            node.tracker.wait().await;
+        }
+    }
+
+    /// This node and all its live descendants, pruning the dead ones along the way.
+    fn subtree(&self) -> Vec<Arc<SupervisedNode>> {
+        let mut nodes = vec![self.0.clone()];
+        let mut i = 0;
+
+        while let Some(node) = nodes.get(i).cloned() {
+            let mut children = node.children.lock().unwrap();
+
+            children.retain(|child| match child.upgrade() {
+                Some(child) => {
+                    nodes.push(child);
+                    true
+                }
+                None => false,
+            });
+            drop(children);
+            i += 1;
+        }
+
+        nodes
+    }
+}
+
+/// A tracker that keeps the output of each task, backed by a
+/// [`JoinSet`](https://docs.rs/tokio/latest/tokio/task/struct.JoinSet.html).
+///
+/// Unlike [`TaskTracker`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html),
+/// which only reports completion, this pairs every result with the [`TaskId`] it came from.
+#[derive(Debug)]
+pub struct ResultTracker<T> {
+    set: JoinSet<T>,
+    results: Vec<(TaskId, AnyRes<T>)>,
+    closed: bool,
+}
+
+impl<T> Default for ResultTracker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ResultTracker<T> {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self {
+            set: JoinSet::new(),
+            results: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// Spawn a task on the current runtime, returning its id.
+    pub fn spawn<F>(&mut self, future: F) -> TaskId
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.set.spawn(future).id().into()
+    }
+
+    /// Close the tracker, like
+    /// [`TaskTracker::close`](https://docs.rs/tokio-util/latest/tokio_util/task/task_tracker/struct.TaskTracker.html#method.close).
+    ///
+    /// As there, this doesn't prevent spawning more tasks, it only marks the tracker as closed.
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Reopen the tracker after it has been closed.
+    pub fn reopen(&mut self) {
+        self.closed = false;
+    }
+
+    /// Whether the tracker is closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Close the tracker and wait for all the spawned tasks to finish, keeping their results for
+    /// [`drain_results`](Self::drain_results).
+    pub async fn close_and_wait(&mut self)
+    where
+        T: 'static,
+    {
+        self.close();
+        while let Some(res) = self.set.join_next_with_id().await {
+            self.results.push(Self::pair(res));
+        }
+    }
+
+    /// Take the results of all the tasks that have finished so far.
+    ///
+    /// A panicked or cancelled task yields an `Err` converted from its
+    /// [`JoinError`](https://docs.rs/tokio/latest/tokio/task/struct.JoinError.html).
+    pub fn drain_results(&mut self) -> Vec<(TaskId, AnyRes<T>)>
+    where
+        T: 'static,
+    {
+        while let Some(res) = self.set.try_join_next_with_id() {
+            self.results.push(Self::pair(res));
+        }
+
+        std::mem::take(&mut self.results)
+    }
+
+    fn pair(res: Result<(tokio::task::Id, T), JoinError>) -> (TaskId, AnyRes<T>) {
+        match res {
+            Ok((id, t)) => (id.into(), Ok(t)),
+            Err(err) => (err.id().into(), Err(err.into())),
+        }
+    }
+}
+
+/// A [`JoinSet`](https://docs.rs/tokio/latest/tokio/task/struct.JoinSet.html) that returns
+/// the results in spawn order, rather than completion order.
+///
+/// Results of tasks that finish early are buffered until
+/// [`join_all_ordered`](Self::join_all_ordered) returns them.
+#[derive(Debug)]
+pub struct OrderedJoinSet<T> {
+    set: JoinSet<T>,
+    indices: HashMap<tokio::task::Id, usize>,
+}
+
+impl<T> Default for OrderedJoinSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> OrderedJoinSet<T> {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self {
+            set: JoinSet::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Spawn a task on the current runtime, returning its id.
+    pub fn spawn<F>(&mut self, future: F) -> TaskId
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let id = self.set.spawn(future).id();
+        self.indices.insert(id, self.indices.len());
+        id.into()
+    }
+
+    /// The number of tasks spawned since the last [`join_all_ordered`](Self::join_all_ordered).
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns `true` if no task was spawned since the last
+    /// [`join_all_ordered`](Self::join_all_ordered).
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Wait for all the spawned tasks to finish, returning their results in spawn order.
+    ///
+    /// A task that panicked or was cancelled gives an `Err`. The set is empty afterwards.
+    pub async fn join_all_ordered(&mut self) -> Vec<AnyRes<T>>
+    where
+        T: 'static,
+    {
+        let mut results: Vec<_> = (0..self.indices.len()).map(|_| None).collect();
+
+        while let Some(res) = self.set.join_next_with_id().await {
+            let (id, res) = match res {
+                Ok((id, t)) => (id, Ok(t)),
+                Err(err) => (err.id(), Err(err.into())),
+            };
+
+            results[self.indices[&id]] = Some(res);
+        }
+
+        self.indices.clear();
+        results
+            .into_iter()
+            .map(|res| res.expect("this should be unreachable"))
+            .collect()
+    }
+}
+
+/// A dynamic group of futures polled together on the current task, backed by a
+/// [`FuturesUnordered`](https://docs.rs/futures-util/latest/futures_util/stream/struct.FuturesUnordered.html).
+///
+/// Each pushed future gets a [`TaskId`], and results are yielded in completion order
+/// together with it. These ids are synthetic (counting from 1 within each group), not the
+/// ids of tokio tasks, since nothing is spawned.
+#[derive(Debug)]
+pub struct FuturesGroup<T> {
+    futures: FuturesUnordered<BoxFuture<'static, (TaskId, T)>>,
+    next_id: NonZeroU64,
+}
+
+impl<T> Default for FuturesGroup<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FuturesGroup<T> {
+    /// Create an empty group.
+    pub fn new() -> Self {
+        Self {
+            futures: FuturesUnordered::new(),
+            next_id: NonZeroU64::MIN,
+        }
+    }
+
+    /// Add a future to the group, returning its id.
+    pub fn push<F>(&mut self, future: F) -> TaskId
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let id = TaskId(self.next_id);
+        self.next_id = self.next_id.checked_add(1).expect("TaskId overflowed");
+        self.futures
+            .push(Box::pin(async move { (id, future.await) }));
+        id
+    }
+
+    /// Wait for the next future to complete, returning its id and output.
+    ///
+    /// Returns `None` if the group is empty.
+    pub async fn next(&mut self) -> Option<(TaskId, T)> {
+        futures_util::StreamExt::next(&mut self.futures).await
+    }
+
+    /// The number of futures that have not completed yet.
+    pub fn len(&self) -> usize {
+        self.futures.len()
+    }
+
+    /// Returns `true` if there is no pending future.
+    pub fn is_empty(&self) -> bool {
+        self.futures.is_empty()
+    }
+}
+
+/// The metadata kept for each task in a [`TaskRegistry`].
+///
+/// It can be `serde`, e.g. to dump a [`snapshot`](TaskRegistry::snapshot) of the registry.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
+pub struct TaskMeta {
+    /// The name of the task.
+    pub name: String,
+    /// When the task was started.
+    pub started: SerdeInstant,
+}
+
+impl TaskMeta {
+    /// Create the metadata of a task named `name`, started now.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            started: SerdeInstant::now(),
+        }
+    }
+}
+
+/// A thread-safe registry of [`TaskMeta`] keyed by [`TaskId`], in insertion order.
+///
+/// Share it with an `Arc` to observe the tasks of a service from anywhere.
+#[derive(Debug, Default)]
+pub struct TaskRegistry(RwLock<IndexMap<TaskId, TaskMeta>>);
+
+impl TaskRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a task, returning the metadata it replaced, if any.
+    pub fn insert(&self, id: TaskId, meta: TaskMeta) -> Option<TaskMeta> {
+        self.0.write().unwrap().insert(id, meta)
+    }
+
+    /// Unregister a task, returning its metadata.
+    pub fn remove(&self, id: TaskId) -> Option<TaskMeta> {
+        self.0.write().unwrap().shift_remove(&id)
+    }
+
+    /// Get a copy of the metadata of a task.
+    pub fn get(&self, id: TaskId) -> Option<TaskMeta> {
+        self.0.read().unwrap().get(&id).cloned()
+    }
+
+    /// Get a copy of the whole registry.
+    pub fn snapshot(&self) -> IndexMap<TaskId, TaskMeta> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+/// Coalesce rapid events, keeping only the latest value.
+///
+/// [`next`](Self::next) resolves with the most recently [`trigger`](Self::trigger)ed value,
+/// once `delay` has elapsed without any new trigger.
+#[derive(Debug)]
+pub struct Debounce<T> {
+    delay: Duration,
+    pending: Mutex<Option<(T, Instant)>>,
+    notify: Notify,
+}
+
+impl<T> Debounce<T> {
+    /// Create a debouncer that waits for `delay` of quiet.
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            pending: Mutex::new(None),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Record a new value, replacing any pending one and restarting the delay.
+    pub fn trigger(&self, value: T) {
+        *self.pending.lock().unwrap() = Some((value, Instant::now() + self.delay));
+        self.notify.notify_one();
+    }
+
+    /// Wait for the latest value, once `delay` has elapsed since the last trigger.
+    pub async fn next(&self) -> T {
+        loop {
+            let deadline = {
+                let mut pending = self.pending.lock().unwrap();
+
+                match &*pending {
+                    Some((_, deadline)) if *deadline <= Instant::now() => {
+                        return pending.take().expect("this should be unreachable").0;
+                    }
+                    Some((_, deadline)) => Some(*deadline),
+                    None => None,
+                }
+            };
+
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => self.notify.notified().await,
+            }
+        }
+    }
+}
+
+/// Detect stalls, such as a stuck event loop, when [`feed`](Self::feed) isn't called in time.
+///
+/// [`monitor`](Self::monitor) is meant to be spawned next to the watched loop, which calls
+/// `feed` on every iteration.
+#[derive(Debug)]
+pub struct Watchdog {
+    timeout: Duration,
+    last_feed: Mutex<Instant>,
+}
+
+impl Watchdog {
+    /// Create a watchdog that considers a stall to be `timeout` without any feed.
+    ///
+    /// The timeout starts counting now.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_feed: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Signal that the watched loop is alive, restarting the timeout.
+    pub fn feed(&self) {
+        *self.last_feed.lock().unwrap() = Instant::now();
+    }
+
+    /// Call `on_stall` whenever `timeout` elapses without a feed. This never returns.
+    ///
+    /// While the stall lasts, `on_stall` is called again once every `timeout`.
+    pub async fn monitor<F: FnMut()>(&self, mut on_stall: F) {
+        loop {
+            let deadline = *self.last_feed.lock().unwrap() + self.timeout;
+            tokio::time::sleep_until(deadline).await;
+
+            let mut last_feed = self.last_feed.lock().unwrap();
+
+            if *last_feed + self.timeout <= Instant::now() {
+                *last_feed = Instant::now();
+                drop(last_feed);
+                on_stall();
+            }
+        }
+    }
+}
+
+/// Run `f` until the absolute `deadline`, wrapping
+/// [`tokio::time::timeout_at`](https://docs.rs/tokio/latest/tokio/time/fn.timeout_at.html).
+///
+/// If the deadline elapses first, an error noting the deadline is returned.
+pub async fn timeout_at_any<F: Future>(deadline: Instant, f: F) -> AnyRes<F::Output> {
+    tokio::time::timeout_at(deadline, f)
+        .await
+        .with_context(|| format!("timeout_at_any: the deadline {deadline:?} has elapsed"))
+}
+
+/// Spawn a producer and a consumer on `tracker`, connected by a bounded
+/// [`mpsc`](https://docs.rs/tokio/latest/tokio/sync/mpsc/index.html) channel of capacity `cap`,
+/// returning their ids.
+///
+/// The consumer sees the end of the channel once the producer drops its sender.
+///
+/// # Panics
+///
+/// Panics if `cap` is 0.
+pub fn pipe<T, P, PF, C, CF>(
+    tracker: &TaskTracker,
+    cap: usize,
+    producer: P,
+    consumer: C,
+) -> (TaskId, TaskId)
+where
+    T: Send + 'static,
+    P: FnOnce(mpsc::Sender<T>) -> PF,
+    PF: Future<Output = ()> + Send + 'static,
+    C: FnOnce(mpsc::Receiver<T>) -> CF,
+    CF: Future<Output = ()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(cap);
+    let consumer = tracker.spawn(consumer(rx)).id().into();
+    let producer = tracker.spawn(producer(tx)).id().into();
+    (producer, consumer)
+}
+
+/// Spawn one tracked task per consumer on `tracker`, each calling its consumer on every
+/// value sent to the returned [`broadcast`](https://docs.rs/tokio/latest/tokio/sync/broadcast/index.html)
+/// channel of capacity `cap`, returning the sender and the consumers' ids.
+///
+/// A consumer that lags behind by more than `cap` values skips the oldest ones. The consumer
+/// tasks finish once every sender is dropped.
+///
+/// # Panics
+///
+/// Panics if `cap` is 0.
+pub fn fanout<T: Clone + Send + 'static>(
+    tracker: &TaskTracker,
+    cap: usize,
+    consumers: Vec<Box<dyn FnMut(T) + Send>>,
+) -> (broadcast::Sender<T>, Vec<TaskId>) {
+    let (tx, _) = broadcast::channel(cap);
+    let ids = consumers
+        .into_iter()
+        .map(|mut consumer| {
+            let mut rx = tx.subscribe();
+
+            tracker
+                .spawn(async move {
+                    loop {
+                        match rx.recv().await {
+                            Ok(value) => consumer(value),
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        }
+                    }
+                })
+                .id()
+                .into()
+        })
+        .collect();
+
+    (tx, ids)
+}
+
+/// Spawn a tracked task on `tracker` from a poll function, via
+/// [`std::future::poll_fn`](https://doc.rust-lang.org/stable/std/future/fn.poll_fn.html),
+/// returning its id.
+pub fn spawn_poll_fn<T, F>(tracker: &TaskTracker, f: F) -> TaskId
+where
+    T: Send + 'static,
+    F: FnMut(&mut std::task::Context<'_>) -> std::task::Poll<T> + Send + 'static,
+{
+    tracker.spawn(std::future::poll_fn(f)).id().into()
+}
+
+/// How long a future took, as measured by [`timed`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TaskTiming {
+    /// The wall time from the first poll until completion.
+    pub wall: Duration,
+    /// The time spent inside `poll`, i.e. actually running rather than waiting.
+    pub poll: Duration,
+    /// The number of times the future was polled.
+    pub polls: usize,
+}
+
+/// Run `f`, measuring its [`TaskTiming`].
+///
+/// A `poll` time close to the `wall` time points at a future that hogs the executor.
+/// Both are measured with [`std::time::Instant`](https://doc.rust-lang.org/stable/std/time/struct.Instant.html),
+/// so they are not affected by a paused tokio clock.
+pub async fn timed<F: Future>(f: F) -> (F::Output, TaskTiming) {
+    let mut f = pin!(f);
+    let mut timing = TaskTiming::default();
+    let start = std::time::Instant::now();
+    let output = std::future::poll_fn(|cx| {
+        let polled = std::time::Instant::now();
+        let res = f.as_mut().poll(cx);
+        timing.poll += polled.elapsed();
+        timing.polls += 1;
+        res
+    })
+    .await;
+
+    timing.wall = start.elapsed();
+    (output, timing)
+}
+
+/// Spawn `f`, measuring the delay between spawning it and its first poll.
+///
+/// A large latency points at a saturated executor. It is measured with
+/// [`std::time::Instant`](https://doc.rust-lang.org/stable/std/time/struct.Instant.html), like
+/// [`timed`], and returned alongside the output of `f`.
+///
+/// The task is spawned when the returned future is first polled, which is also when the
+/// measurement starts.
+pub async fn spawn_with_latency<F>(f: F) -> (TaskId, JoinHandle<(Duration, F::Output)>)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let spawned = std::time::Instant::now();
+    let handle = tokio::spawn(async move {
+        let latency = spawned.elapsed();
+        (latency, f.await)
+    });
+
+    (handle.id().into(), handle)
+}
+
+/// A tiny [SplitMix64](https://prng.di.unimi.it/splitmix64.c) generator, so that jitter is
+/// reproducible for a given seed without pulling in `rand`.
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// The delays of a jittered interval, uniformly within `base ± jitter` (clamped at zero).
+fn jittered_delays(seed: u64, base: Duration, jitter: Duration) -> impl Iterator<Item = Duration> {
+    let mut rng = SplitMix64(seed);
+    let span = u64::try_from(jitter.as_nanos())
+        .unwrap_or(u64::MAX / 2)
+        .saturating_mul(2);
+
+    std::iter::repeat_with(move || {
+        let offset = Duration::from_nanos(rng.next_u64() % span.saturating_add(1));
+        base.saturating_add(offset).saturating_sub(jitter)
+    })
+}
+
+/// Spawn a task on `tracker` that calls `f` repeatedly, waiting `base ± random(jitter)` before
+/// each call, returning its id.
+///
+/// This spreads out periodic work that would otherwise fire simultaneously. The randomness
+/// comes from a small deterministic generator seeded with the task's id; use
+/// [`spawn_interval_jittered_seeded`] to choose the seed. Each call is awaited before waiting
+/// for the next one.
+///
+/// The task stops as soon as `cancel` is cancelled, even in the middle of a delay, or on the
+/// next tick once `tracker` is closed. Cancelling `cancel` before
+/// [`close_and_wait`](CloseAndWait::close_and_wait) avoids waiting for the current delay.
+pub fn spawn_interval_jittered<F, Fut>(
+    tracker: &TaskTracker,
+    base: Duration,
+    jitter: Duration,
+    cancel: CancellationToken,
+    f: F,
+) -> TaskId
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    spawn_jittered(tracker, base, jitter, None, cancel, f)
+}
+
+/// Like [`spawn_interval_jittered`], but seeding the generator with `seed`, so the sequence
+/// of delays is reproducible.
+pub fn spawn_interval_jittered_seeded<F, Fut>(
+    tracker: &TaskTracker,
+    base: Duration,
+    jitter: Duration,
+    seed: u64,
+    cancel: CancellationToken,
+    f: F,
+) -> TaskId
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    spawn_jittered(tracker, base, jitter, Some(seed), cancel, f)
+}
+
+fn spawn_jittered<F, Fut>(
+    tracker: &TaskTracker,
+    base: Duration,
+    jitter: Duration,
+    seed: Option<u64>,
+    cancel: CancellationToken,
+    mut f: F,
+) -> TaskId
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let closed = tracker.clone();
+
+    tracker
+        .spawn(async move {
+            let seed = seed.unwrap_or_else(|| TaskId::from(tokio::task::id()).0.get());
+
+            for delay in jittered_delays(seed, base, jitter) {
+                let slept = cancel.run_until_cancelled(tokio::time::sleep(delay)).await;
+
+                if slept.is_none() || closed.is_closed() {
+                    break;
+                }
+
+                f().await;
+            }
+        })
+        .id()
+        .into()
+}
+
+/// A stream that yields the current [`SerdeInstant`] once every `period`.
+///
+/// The first tick completes immediately, like
+/// [`tokio::time::interval`](https://docs.rs/tokio/latest/tokio/time/fn.interval.html).
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+#[cfg(feature = "stream")]
+pub fn every(period: Duration) -> impl tokio_stream::Stream<Item = SerdeInstant> {
+    use tokio_stream::StreamExt;
+
+    tokio_stream::wrappers::IntervalStream::new(tokio::time::interval(period)).map(SerdeInstant)
+}
+
+/// Coalesce bursts of `stream`, yielding only the latest item once `window` has elapsed
+/// without a new one, like [`Debounce`] for streams.
+///
+/// When `stream` ends, the pending item (if any) is yielded right away.
+///
+/// This resolves immediately; nothing is polled until the returned stream is.
+pub async fn coalesce<S: Stream>(stream: S, window: Duration) -> impl Stream<Item = S::Item> {
+    use futures_util::StreamExt;
+
+    futures_util::stream::unfold(
+        (Box::pin(stream), false),
+        move |(mut stream, mut done)| async move {
+            if done {
+                return None;
+            }
+
+            let mut latest = stream.next().await?;
+
+            loop {
+                match tokio::time::timeout(window, stream.next()).await {
+                    Ok(Some(item)) => latest = item,
+                    Ok(None) => {
+                        done = true;
+                        break;
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            Some((latest, (stream, done)))
+        },
+    )
+}
+
+/// Wait until a shutdown signal is received.
+///
+/// On Unix, this resolves on either `SIGINT` (Ctrl-C) or `SIGTERM`. On other platforms
+/// (such as Windows), only Ctrl-C is listened to.
+///
+/// Combined with [`CloseAndWait::close_and_wait`], this gives a standard graceful shutdown flow.
+///
+/// Returns an error if the signal handlers could not be registered.
+#[cfg(feature = "signal")]
+pub async fn wait_for_shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::task::Poll;
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut ctrl_c = pin!(tokio::signal::ctrl_c());
+
+        std::future::poll_fn(|cx| match ctrl_c.as_mut().poll(cx) {
+            Poll::Ready(res) => Poll::Ready(res),
+            Poll::Pending => terminate.poll_recv(cx).map(|_| Ok(())),
+        })
+        .await
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+/// Run `main` until it completes or a shutdown signal is received (see
+/// [`wait_for_shutdown_signal`]), then close `tracker` and wait up to `grace` for its tasks.
+///
+/// This is [`run_until_shutdown`] with the shutdown signal as the trigger. Returns an error if
+/// the signal handlers could not be registered, or if tasks are still running once `grace` has
+/// elapsed.
+#[cfg(feature = "signal")]
+pub async fn run_until_signal<F: Future>(
+    tracker: &TaskTracker,
+    main: F,
+    grace: Duration,
+) -> AnyRes<()> {
+    let shutdown = async {
+        wait_for_shutdown_signal()
+            .await
+            .context("run_until_signal: failed to listen for the shutdown signal")
+    };
+
+    run_until_shutdown(tracker, main, shutdown, grace).await
+}
+
+/// Run `main` until it completes or `shutdown` resolves, then close `tracker` and wait up to
+/// `grace` for its tasks.
+///
+/// An error returned by `shutdown` is propagated right away, without closing `tracker`.
+/// Otherwise, returns an error if tasks are still running once `grace` has elapsed.
+pub async fn run_until_shutdown<F, S>(
+    tracker: &TaskTracker,
+    main: F,
+    shutdown: S,
+    grace: Duration,
+) -> AnyRes<()>
+where
+    F: Future,
+    S: Future<Output = AnyRes<()>>,
+{
+    use futures_util::future::{select, Either};
+
+    if let Either::Right((res, _)) = select(pin!(main), pin!(shutdown)).await {
+        res?;
+    }
+
+    tokio::time::timeout(grace, tracker.close_and_wait())
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "run_until_shutdown: {} tasks are still running after the grace period of {grace:?}",
+                tracker.len()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn from_tokio_task_id() {
+        let id = tokio::spawn(async { tokio::task::id() }).await.unwrap();
+        assert_eq!(id.to_string(), TaskId::from(id).to_string());
+    }
+
+    #[tokio::test]
+    async fn serde_instant() {
+        let close =
+            |a: SerdeInstant, b: SerdeInstant| a.0.max(b.0) - a.0.min(b.0) < Duration::from_secs(1);
+
+        for instant in [
+            SerdeInstant::now(),
+            (Instant::now() - Duration::from_secs(60)).into(),
+            (Instant::now() + Duration::from_secs(60)).into(),
+        ] {
+            let round_trip = ron::from_str(&ron::to_string(&instant).unwrap()).unwrap();
+            assert!(close(instant, round_trip));
+        }
+
+        let instant = SerdeInstant::now();
+        let system: SystemTime = ron::from_str(&ron::to_string(&instant).unwrap()).unwrap();
+        let elapsed = SystemTime::now().duration_since(system).unwrap();
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    fn tracker_spawn() -> TaskTracker {
+        let tracker = TaskTracker::new();
+
+        for i in 0..3 {
+            tracker.spawn(async move { i });
+        }
+
+        tracker
+    }
+
+    #[tokio::test]
+    async fn close_and_wait() {
+        use std::time::Duration;
+        use tokio::time::timeout;
+
+        let tracker = tracker_spawn();
+        assert!(timeout(Duration::from_secs_f64(1.5), tracker.wait())
+            .await
+            .is_err());
+
+        let tracker = tracker_spawn();
+        tracker.close();
+        assert!(timeout(Duration::from_secs_f64(1.5), tracker.wait())
+            .await
+            .is_ok());
+
+        let tracker = tracker_spawn();
+        assert!(
+            timeout(Duration::from_secs_f64(1.5), tracker.close_and_wait())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn reopen() {
+        use std::time::Duration;
+        use tokio::time::timeout;
+
+        let tracker = tracker_spawn();
+        assert!(!CloseAndWait::reopen(&tracker));
+        assert!(
+            timeout(Duration::from_secs_f64(1.5), tracker.close_and_wait())
+                .await
+                .is_ok()
+        );
+        assert!(tracker.is_closed());
+        assert!(CloseAndWait::reopen(&tracker));
+        assert!(!tracker.is_closed());
+
+        for i in 0..3 {
+            tracker.spawn(async move { i });
+        }
+
+        assert!(timeout(Duration::from_secs_f64(1.5), tracker.wait())
+            .await
+            .is_err());
+        assert!(
+            timeout(Duration::from_secs_f64(1.5), tracker.close_and_wait())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn close_wait_reopen() {
+        use std::time::Duration;
+        use tokio::time::timeout;
+
+        let tracker = tracker_spawn();
+        assert!(
+            timeout(Duration::from_secs_f64(1.5), tracker.close_wait_reopen())
+                .await
+                .is_ok()
+        );
+        assert!(!tracker.is_closed());
+        assert!(tracker.is_empty());
+
+        for i in 0..3 {
+            tracker.spawn(async move { i });
+        }
+
+        assert!(
+            timeout(Duration::from_secs_f64(1.5), tracker.close_wait_reopen())
+                .await
+                .is_ok()
+        );
+        assert!(!tracker.is_closed());
+        assert!(tracker.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_with_progress() {
+        use tokio::time::sleep;
+
+        let tracker = TaskTracker::new();
+
+        for i in 1..=3 {
+            tracker.spawn(sleep(Duration::from_millis(100) * i));
+        }
+
+        let mut counts = Vec::new();
+        tracker
+            .wait_with_progress(Duration::from_millis(70), |n| counts.push(n))
+            .await;
+
+        assert_eq!(counts, [3, 2, 1, 1]);
+        assert!(tracker.is_closed());
+        assert!(tracker.is_empty());
+    }
+
+    #[tokio::test]
+    async fn named_tracker() {
+        use tokio::sync::oneshot;
+
+        let tracker = NamedTracker::new();
+        let other = NamedTracker::new();
+        let (tx, rx) = oneshot::channel::<()>();
+
+        let id1 = tracker.spawn("worker-1", async { rx.await.ok() });
+        let id2 = tracker.spawn("worker-2", std::future::pending::<()>());
+        let id3 = other.spawn("other", std::future::pending::<()>());
+
+        assert_eq!(tracker.len(), 2);
+        assert_eq!(
+            tracker.named_tasks(),
+            IndexMap::from([(id1, "worker-1".to_string()), (id2, "worker-2".to_string())])
+        );
+        assert_eq!(
+            other.named_tasks(),
+            IndexMap::from([(id3, "other".to_string())])
+        );
+
+        tx.send(()).unwrap();
+        while tracker.named_tasks().contains_key(&id1) {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(
+            tracker.clone().named_tasks(),
+            IndexMap::from([(id2, "worker-2".to_string())])
+        );
+        assert!(NamedTracker::new().named_tasks().is_empty());
+
+        let tracker = NamedTracker::new();
+        for i in 0..10 {
+            tracker.spawn(format!("short-{i}"), async {});
+        }
+
+        tracker.close_and_wait().await;
+        assert!(tracker.is_empty());
+        assert!(tracker.named_tasks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pipe() {
+        let tracker = TaskTracker::new();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let (producer, consumer) = super::pipe(
+            &tracker,
+            4,
+            |tx| async move {
+                for i in 0..100 {
+                    tx.send(i).await.unwrap();
+                }
+            },
+            |mut rx| async move {
+                while let Some(i) = rx.recv().await {
+                    sink.lock().unwrap().push(i);
+                }
+            },
+        );
+
+        assert_ne!(producer, consumer);
+        assert_eq!(tracker.len(), 2);
+        tracker.close_and_wait().await;
+        assert_eq!(*received.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn fanout() {
+        let tracker = TaskTracker::new();
+        let received: Vec<_> = (0..3).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+        let consumers = received
+            .iter()
+            .map(|sink| {
+                let sink = sink.clone();
+                Box::new(move |i| sink.lock().unwrap().push(i)) as Box<dyn FnMut(i32) + Send>
+            })
+            .collect();
+        let (tx, ids) = super::fanout(&tracker, 16, consumers);
+
+        assert_eq!(ids.len(), 3);
+        assert_eq!(tx.receiver_count(), 3);
+
+        for i in 0..10 {
+            tx.send(i).unwrap();
+        }
+
+        drop(tx);
+        tracker.close_and_wait().await;
+
+        for sink in received {
+            assert_eq!(*sink.lock().unwrap(), (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn spawn_poll_fn() {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            task::Poll,
+        };
+
+        let tracker = TaskTracker::new();
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+
+        super::spawn_poll_fn(&tracker, move |cx| {
+            if counter.fetch_add(1, Ordering::SeqCst) < 3 {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        });
+
+        tracker.close_and_wait().await;
+        assert_eq!(polls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn timed() {
+        let busy = || {
+            let start = std::time::Instant::now();
+            while start.elapsed() < Duration::from_millis(5) {
+                std::hint::spin_loop();
+            }
+        };
+
+        let (output, timing) = super::timed(async {
+            for _ in 0..3 {
+                busy();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+
+            7
+        })
+        .await;
+
+        assert_eq!(output, 7);
+        assert_eq!(timing.polls, 4);
+        assert!(timing.poll >= Duration::from_millis(15));
+        assert!(timing.wall >= timing.poll + Duration::from_millis(60));
+
+        let (_, timing) = super::timed(async {}).await;
+        assert_eq!(timing.polls, 1);
+    }
+
+    #[tokio::test]
+    async fn spawn_with_latency() {
+        let (id, handle) = super::spawn_with_latency(async { 7 }).await;
+        assert_eq!(id, handle.id().into());
+
+        let (latency, output) = handle.await.unwrap();
+        assert_eq!(output, 7);
+        assert!(latency < Duration::from_secs(1));
+
+        let (_, handle) = super::spawn_with_latency(async {}).await;
+        let start = std::time::Instant::now();
+        while start.elapsed() < Duration::from_millis(20) {
+            std::hint::spin_loop();
+        }
+
+        let (latency, ()) = handle.await.unwrap();
+        assert!(latency >= Duration::from_millis(20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawn_interval_jittered() {
+        let (base, jitter) = (Duration::from_secs(1), Duration::from_millis(200));
+        let delays: Vec<_> = jittered_delays(7, base, jitter).take(100).collect();
+
+        assert_eq!(
+            delays,
+            jittered_delays(7, base, jitter)
+                .take(100)
+                .collect::<Vec<_>>()
+        );
+        assert_ne!(
+            delays,
+            jittered_delays(8, base, jitter)
+                .take(100)
+                .collect::<Vec<_>>()
+        );
+        assert!(delays.iter().any(|d| *d != delays[0]));
+        assert!(jittered_delays(1, base, Duration::ZERO)
+            .take(10)
+            .all(|d| d == base));
+        assert_eq!(
+            jittered_delays(3, Duration::MAX, Duration::MAX)
+                .take(100)
+                .count(),
+            100
+        );
+        assert!(jittered_delays(3, Duration::MAX, Duration::from_secs(1))
+            .take(100)
+            .all(|d| d >= Duration::MAX - Duration::from_secs(1)));
+        assert!(
+            jittered_delays(3, Duration::MAX, Duration::from_secs(u64::MAX / 2))
+                .take(100)
+                .all(|d| d >= Duration::from_secs(u64::MAX / 2))
+        );
+
+        let tracker = TaskTracker::new();
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let start = Instant::now();
+        let recorded = ticks.clone();
+        super::spawn_interval_jittered(
+            &tracker,
+            base,
+            jitter,
+            CancellationToken::new(),
+            move || {
+                recorded.lock().unwrap().push(Instant::now());
+                async {}
+            },
+        );
+
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(tracker.len(), 1);
+        tracker.close_and_wait().await;
+
+        let ticks = ticks.lock().unwrap();
+        let mut last = start;
+        assert!(ticks.len() >= 16);
+
+        for tick in ticks.iter() {
+            let interval = *tick - last;
+            assert!(interval >= base - jitter && interval <= base + jitter);
+            last = *tick;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawn_interval_jittered_seeded() {
+        let (base, jitter) = (Duration::from_secs(1), Duration::from_millis(200));
+
+        async fn record(seed: u64, base: Duration, jitter: Duration) -> Vec<Duration> {
+            let tracker = TaskTracker::new();
+            let cancel = CancellationToken::new();
+            let ticks = Arc::new(Mutex::new(Vec::new()));
+            let start = Instant::now();
+            let recorded = ticks.clone();
+            super::spawn_interval_jittered_seeded(
+                &tracker,
+                base,
+                jitter,
+                seed,
+                cancel.clone(),
+                move || {
+                    recorded.lock().unwrap().push(Instant::now());
+                    async {}
+                },
+            );
+
+            tokio::time::sleep(Duration::from_millis(10_500)).await;
+            cancel.cancel();
+            tracker.close_and_wait().await;
+            assert_eq!(Instant::now() - start, Duration::from_millis(10_500));
+
+            let ticks = ticks.lock().unwrap();
+            let mut last = start;
+            ticks
+                .iter()
+                .map(|tick| {
+                    let interval = *tick - last;
+                    last = *tick;
+                    interval
+                })
+                .collect()
+        }
+
+        let delays = record(42, base, jitter).await;
+        // Tokio's timer rounds the deadlines up to the next millisecond.
+        let expected: Vec<_> = jittered_delays(42, base, jitter)
+            .take(delays.len())
+            .map(|d| Duration::from_millis(d.as_nanos().div_ceil(1_000_000) as u64))
+            .collect();
+
+        assert!(delays.len() >= 8);
+        assert_eq!(delays, expected);
+        assert_eq!(record(42, base, jitter).await, delays);
+        assert_ne!(record(43, base, jitter).await, delays);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_at_any() {
+        use tokio::time::sleep;
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let res = super::timeout_at_any(deadline, async {
+            sleep(Duration::from_millis(500)).await;
+            5
+        })
+        .await;
+        assert_eq!(res.unwrap(), 5);
+
+        let res = super::timeout_at_any(deadline, sleep(Duration::from_secs(5))).await;
+        let err = res.unwrap_err();
+        assert!(err.to_string().contains("deadline"));
+        assert!(err.root_cause().is::<tokio::time::error::Elapsed>());
+        assert_eq!(Instant::now(), deadline);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawn_limited() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::time::sleep;
+
+        let tracker = TaskTracker::new();
+        let sem = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+        let mut ids = Vec::new();
+
+        for _ in 0..6 {
+            let running = running.clone();
+            let max = max.clone();
+
+            ids.push(
+                tracker
+                    .spawn_limited(&sem, async move {
+                        let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max.fetch_max(n, Ordering::SeqCst);
+                        sleep(Duration::from_millis(10)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                    .await,
+            );
+        }
+
+        tracker.close_and_wait().await;
+        assert_eq!(max.load(Ordering::SeqCst), 2);
+        assert_eq!(sem.available_permits(), 2);
+        assert_eq!(ids.len(), 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn supervised_tracker() {
+        use tokio::time::sleep;
+
+        let parent = SupervisedTracker::new();
+        let child = parent.child();
+        let grandchild = child.child();
+        let start = Instant::now();
+
+        parent.spawn(sleep(Duration::from_millis(100)));
+        child.spawn(sleep(Duration::from_millis(200)));
+        grandchild.spawn(sleep(Duration::from_millis(300)));
+        parent.child().spawn(sleep(Duration::from_millis(400)));
+        drop(grandchild);
+        assert_eq!(parent.subtree().len(), 4);
+
+        parent.close_and_wait_recursive().await;
+        assert_eq!(start.elapsed(), Duration::from_millis(400));
+        assert!(parent.is_closed() && parent.is_empty());
+        assert!(child.is_closed() && child.is_empty());
+        assert_eq!(parent.subtree().len(), 2);
+
+        let other = SupervisedTracker::new();
+        other.spawn(sleep(Duration::from_millis(100)));
+        child.close_and_wait_recursive().await;
+        assert!(!other.is_closed());
+
+        drop(child);
+        for _ in 0..10 {
+            drop(parent.child());
+        }
+
+        assert_eq!(parent.0.children.lock().unwrap().len(), 1);
+        assert_eq!(parent.subtree().len(), 1);
+        assert!(parent.0.children.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawn_after() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let tracker = TaskTracker::new();
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+        let start = Instant::now();
+
+        tracker.spawn_after(Duration::from_millis(500), async move {
+            flag.store(true, Ordering::SeqCst);
+        });
+        assert_eq!(tracker.len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(499)).await;
+        assert!(!ran.load(Ordering::SeqCst));
+
+        tracker.close_and_wait().await;
+        assert!(ran.load(Ordering::SeqCst));
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limited_tracker() {
+        use tokio::time::sleep;
+
+        let tracker = LimitedTracker::new(2);
+        let start = Instant::now();
+
+        assert!(tracker
+            .try_spawn(sleep(Duration::from_millis(100)))
+            .is_some());
+        assert!(tracker
+            .try_spawn(sleep(Duration::from_millis(200)))
+            .is_some());
+        assert!(tracker.try_spawn(async {}).is_none());
+        assert_eq!(tracker.len(), 2);
+
+        tracker.spawn(sleep(Duration::from_millis(100))).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+        assert!(tracker.try_spawn(async {}).is_none());
+
+        sleep(Duration::from_millis(101)).await;
+        assert!(tracker.try_spawn(async {}).is_some());
+
+        tracker.close_and_wait().await;
+        assert!(tracker.is_empty());
+        assert_eq!(start.elapsed(), Duration::from_millis(201));
+    }
+
+    #[tokio::test]
+    async fn result_tracker() {
+        let mut tracker = ResultTracker::new();
+        let ids = [
+            tracker.spawn(async { 1 }),
+            tracker.spawn(async { panic!("boom") }),
+            tracker.spawn(async { 3 }),
+        ];
+
+        assert!(!tracker.is_closed());
+        tracker.close_and_wait().await;
+        assert!(tracker.is_closed());
+        let mut results = tracker.drain_results();
+        results.sort_by_key(|(id, _)| ids.iter().position(|i| i == id));
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, ids[0]);
+        assert_eq!(results[0].1.as_ref().unwrap(), &1);
+        assert_eq!(results[1].0, ids[1]);
+        assert!(results[1]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("panic"));
+        assert_eq!(results[2].0, ids[2]);
+        assert_eq!(results[2].1.as_ref().unwrap(), &3);
+        assert!(tracker.drain_results().is_empty());
+
+        tracker.reopen();
+        assert!(!tracker.is_closed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ordered_join_set() {
+        use tokio::time::sleep;
+
+        let mut set = OrderedJoinSet::new();
+
+        for (i, ms) in [300, 100, 200, 0].into_iter().enumerate() {
+            set.spawn(async move {
+                sleep(Duration::from_millis(ms)).await;
+                assert_ne!(i, 2, "boom");
+                i
+            });
+        }
+
+        assert_eq!(set.len(), 4);
+
+        let results = set.join_all_ordered().await;
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), &0);
+        assert_eq!(results[1].as_ref().unwrap(), &1);
+        assert!(format!("{:#}", results[2].as_ref().unwrap_err()).contains("panic"));
+        assert_eq!(results[3].as_ref().unwrap(), &3);
+
+        assert!(set.is_empty());
+        set.spawn(async { 7 });
+        assert_eq!(set.join_all_ordered().await.pop().unwrap().unwrap(), 7);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn futures_group() {
+        use tokio::time::sleep;
+
+        let mut group = FuturesGroup::new();
+        let ids: Vec<_> = [300, 100, 200]
+            .into_iter()
+            .map(|ms| {
+                group.push(async move {
+                    sleep(Duration::from_millis(ms)).await;
+                    ms
+                })
+            })
+            .collect();
+
+        assert_eq!(group.len(), 3);
+        assert_eq!(ids[0].to_string(), "1");
+        assert_eq!(group.next().await, Some((ids[1], 100)));
+        assert_eq!(group.next().await, Some((ids[2], 200)));
+
+        let late = group.push(async { 0 });
+        assert_eq!(group.next().await, Some((late, 0)));
+        assert_eq!(group.next().await, Some((ids[0], 300)));
+        assert!(group.is_empty());
+        assert_eq!(group.next().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn task_registry() {
+        use tokio::time::sleep;
+
+        let registry = Arc::new(TaskRegistry::new());
+        let start = Instant::now();
+        let mut set = JoinSet::new();
+
+        for i in 0..4 {
+            let registry = registry.clone();
+
+            set.spawn(async move {
+                sleep(Duration::from_millis(i * 100)).await;
+                let id = tokio::task::id().into();
+                registry.insert(id, TaskMeta::new(format!("task-{i}")));
+                id
+            });
+        }
+
+        let ids = set.join_all().await;
+        let snapshot = registry.snapshot();
+
+        assert_eq!(snapshot.keys().copied().collect::<Vec<_>>(), ids);
+        for (i, meta) in snapshot.values().enumerate() {
+            assert_eq!(meta.name, format!("task-{i}"));
+            assert_eq!(
+                meta.started.0 - start,
+                Duration::from_millis(i as u64 * 100)
+            );
+        }
+
+        assert_eq!(registry.get(ids[1]), Some(snapshot[1].clone()));
+        assert_eq!(registry.remove(ids[1]), Some(snapshot[1].clone()));
+        assert_eq!(registry.get(ids[1]), None);
+        assert_eq!(registry.remove(ids[1]), None);
+        assert_eq!(registry.snapshot().len(), 3);
+        assert_eq!(snapshot.len(), 4);
+
+        let dump: Vec<_> = snapshot.into_iter().collect();
+        let loaded: Vec<(TaskId, TaskMeta)> =
+            ron::from_str(&ron::to_string(&dump).unwrap()).unwrap();
+        assert_eq!(loaded.len(), 4);
+        for ((id1, meta1), (id2, meta2)) in dump.iter().zip(&loaded) {
+            let (a, b) = (meta1.started.0, meta2.started.0);
+            assert_eq!((id1, &meta1.name), (id2, &meta2.name));
+            assert!(a.max(b) - a.min(b) < Duration::from_secs(1));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn debounce() {
+        use std::sync::Arc;
+        use tokio::time::sleep;
+
+        let debounce = Arc::new(Debounce::new(Duration::from_millis(100)));
+        let start = Instant::now();
+        let trigger = debounce.clone();
+
+        tokio::spawn(async move {
+            for i in 1..=3 {
+                trigger.trigger(i);
+                sleep(Duration::from_millis(50)).await;
+            }
+        });
+
+        assert_eq!(debounce.next().await, 3);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(start.elapsed() < Duration::from_millis(250));
+
+        let trigger = debounce.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(500)).await;
+            trigger.trigger(4);
+        });
+
+        let start = Instant::now();
+        assert_eq!(debounce.next().await, 4);
+        assert!(start.elapsed() >= Duration::from_millis(600));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watchdog() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::time::sleep;
+
+        let watchdog = Arc::new(Watchdog::new(Duration::from_millis(100)));
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let (monitor, counter) = (watchdog.clone(), stalls.clone());
+        let handle = tokio::spawn(async move {
+            monitor
+                .monitor(|| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                })
+                .await
+        });
+
+        for _ in 0..10 {
+            sleep(Duration::from_millis(50)).await;
+            watchdog.feed();
+        }
+        assert_eq!(stalls.load(Ordering::SeqCst), 0);
+
+        sleep(Duration::from_millis(120)).await;
+        assert_eq!(stalls.load(Ordering::SeqCst), 1);
+
+        sleep(Duration::from_millis(200)).await;
+        assert_eq!(stalls.load(Ordering::SeqCst), 3);
+
+        watchdog.feed();
+        sleep(Duration::from_millis(90)).await;
+        assert_eq!(stalls.load(Ordering::SeqCst), 3);
+
+        handle.abort();
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test(start_paused = true)]
+    async fn every() {
+        use tokio::time::timeout_at;
+        use tokio_stream::StreamExt;
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(1050);
+        let mut ticks = pin!(super::every(Duration::from_millis(100)));
+        let mut count = 0;
+
+        while let Ok(Some(tick)) = timeout_at(deadline, ticks.next()).await {
+            assert_eq!(tick.0, start + Duration::from_millis(100) * count);
+            count += 1;
+        }
+
+        assert_eq!(count, 11);
+        let ticks: Vec<_> = super::every(Duration::from_secs(1)).take(3).collect().await;
+        assert_eq!(ticks.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn coalesce() {
+        use futures_util::{stream, StreamExt as _};
+
+        let gaps = [(0, 1), (10, 2), (10, 3), (180, 4), (10, 5), (290, 6)];
+        let events = stream::iter(gaps).then(|(gap, item)| async move {
+            tokio::time::sleep(Duration::from_millis(gap)).await;
+            item
+        });
+        let start = Instant::now();
+        let coalesced = super::coalesce(events, Duration::from_millis(50))
+            .await
+            .map(|item| (item, start.elapsed().as_millis()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(coalesced, vec![(3, 70), (5, 260), (6, 500)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_until_shutdown() {
+        use tokio::{sync::oneshot, time::sleep};
+
+        let tracker = TaskTracker::new();
+        tracker.spawn(sleep(Duration::from_millis(50)));
+
+        let shutdown = std::future::pending();
+        let res = super::run_until_shutdown(&tracker, async {}, shutdown, Duration::from_secs(5));
+        assert!(res.await.is_ok());
+        assert!(tracker.is_closed() && tracker.is_empty());
+
+        let tracker = TaskTracker::new();
+        tracker.spawn(sleep(Duration::from_secs(60)));
+
+        let shutdown = std::future::pending();
+        let err =
+            super::run_until_shutdown(&tracker, async {}, shutdown, Duration::from_millis(50))
+                .await
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "run_until_shutdown: 1 tasks are still running after the grace period of 50ms"
+        );
+
+        let tracker = TaskTracker::new();
+        let (tx, rx) = oneshot::channel();
+        let start = Instant::now();
+        tracker.spawn(sleep(Duration::from_millis(50)));
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            tx.send(()).unwrap();
+        });
+
+        let shutdown = async { Ok(rx.await?) };
+        let main = std::future::pending::<()>();
+        let res = super::run_until_shutdown(&tracker, main, shutdown, Duration::from_secs(5));
+        assert!(res.await.is_ok());
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert!(tracker.is_closed() && tracker.is_empty());
+
+        let tracker = TaskTracker::new();
+        let shutdown = async { Err(anyhow::anyhow!("no signal")) };
+        let main = std::future::pending::<()>();
+        let res = super::run_until_shutdown(&tracker, main, shutdown, Duration::from_secs(5));
+        assert_eq!(res.await.unwrap_err().to_string(), "no signal");
+        assert!(!tracker.is_closed());
+    }
+
+    /// Serializes the tests raising signals in the test process itself.
+    #[cfg(all(unix, feature = "signal"))]
+    static SIGNAL_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    #[cfg(all(unix, feature = "signal"))]
+    fn raise_sigterm() {
+        // SAFETY: `raise` has no preconditions, and the tests only call this once a handler
+        // for `SIGTERM` is registered, so it doesn't terminate the process.
+        assert_eq!(unsafe { libc::raise(libc::SIGTERM) }, 0);
+    }
+
+    #[cfg(all(unix, feature = "signal"))]
+    #[tokio::test]
+    async fn wait_for_shutdown_signal() {
+        use tokio::time::timeout;
+
+        let _lock = SIGNAL_LOCK.lock().await;
+        let mut signal = pin!(super::wait_for_shutdown_signal());
+        assert!(timeout(Duration::from_millis(100), &mut signal)
+            .await
+            .is_err());
+
+        raise_sigterm();
+        assert!(timeout(Duration::from_secs(5), signal)
+            .await
+            .unwrap()
+            .is_ok());
+    }
+
+    #[cfg(all(unix, feature = "signal"))]
+    #[tokio::test]
+    async fn run_until_signal() {
+        use tokio::time::{sleep, timeout};
+
+        let _lock = SIGNAL_LOCK.lock().await;
+        let tracker = TaskTracker::new();
+        let mut run = pin!(super::run_until_signal(
+            &tracker,
+            std::future::pending::<()>(),
+            Duration::from_secs(5),
+        ));
+        assert!(timeout(Duration::from_millis(100), &mut run).await.is_err());
+
+        tracker.spawn(sleep(Duration::from_millis(50)));
+        raise_sigterm();
+        assert!(timeout(Duration::from_secs(5), run).await.unwrap().is_ok());
+        assert!(tracker.is_closed() && tracker.is_empty());
+    }
+
+    #[test]
+    fn parse_task_id() {
+        assert_eq!(
+            "42".parse::<TaskId>(),
+            Ok(TaskId(NonZeroU64::new(42).unwrap()))
+        );
+        assert_eq!("0".parse::<TaskId>(), Err(TaskIdParseErr::Zero));
+        assert_eq!("".parse::<TaskId>(), Err(TaskIdParseErr::Empty));
+        assert_eq!("abc".parse::<TaskId>(), Err(TaskIdParseErr::Invalid));
+        assert_eq!("-1".parse::<TaskId>(), Err(TaskIdParseErr::Invalid));
+
+        let id = TaskId(NonZeroU64::new(7).unwrap());
+        assert_eq!(id.to_string().parse::<TaskId>(), Ok(id));
     }
 }
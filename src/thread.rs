@@ -1,6 +1,22 @@
+use crate::AnyRes;
+use anyhow::Context;
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
-use std::num::NonZeroU64;
+use std::{
+    any::Any,
+    cell::Cell,
+    collections::HashMap,
+    hint,
+    num::{IntErrorKind, NonZeroU64},
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    str::FromStr,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+};
+use thiserror::Error;
 
 /// A [`ThreadId`](https://doc.rust-lang.org/stable/std/thread/struct.ThreadId.html) that can be `serde` and `Display`ed
 #[derive(Debug, Display, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash)]
@@ -17,6 +33,377 @@ impl From<std::thread::ThreadId> for ThreadId {
     }
 }
 
+/// Error returned when parsing a [`ThreadId`] from a string.
+#[derive(Error, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ThreadIdParseErr {
+    #[error("parse ThreadId: the input is empty")]
+    /// The input is empty.
+    Empty,
+    #[error("parse ThreadId: the id is zero")]
+    /// The id is zero.
+    Zero,
+    #[error("parse ThreadId: the input is not a valid decimal id")]
+    /// The input is not a valid decimal id (or is too large).
+    Invalid,
+}
+
+impl FromStr for ThreadId {
+    type Err = ThreadIdParseErr;
+
+    /// Parse a decimal id, as produced by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self)
+            .map_err(|err: std::num::ParseIntError| match err.kind() {
+                IntErrorKind::Empty => ThreadIdParseErr::Empty,
+                IntErrorKind::Zero => ThreadIdParseErr::Zero,
+                _ => ThreadIdParseErr::Invalid,
+            })
+    }
+}
+
+/// A label for the current thread, for logging.
+///
+/// It looks like `ThreadId(3) [worker-1]` for named threads, or just `ThreadId(3)` otherwise.
+pub fn describe_current() -> String {
+    let thread = std::thread::current();
+    let id = ThreadId::from(thread.id());
+
+    match thread.name() {
+        Some(name) => format!("{id:?} [{name}]"),
+        None => format!("{id:?}"),
+    }
+}
+
+/// Spawn a named thread running `f`, restarting it whenever it panics.
+///
+/// With the `tracing` feature, each restart is logged with
+/// [`tracing::warn!`](https://docs.rs/tracing/latest/tracing/macro.warn.html) and the thread's
+/// id. After `max_restarts` restarts, the next panic is propagated, so joining the returned
+/// handle yields an `Err`. Otherwise, joining it yields the number of restarts.
+///
+/// Use [`spawn_supervised_with`] to observe the restarts without `tracing`.
+///
+/// # Panics
+///
+/// Panics if the OS fails to create the thread, like
+/// [`std::thread::spawn`](https://doc.rust-lang.org/stable/std/thread/fn.spawn.html).
+pub fn spawn_supervised<F>(
+    name: impl Into<String>,
+    max_restarts: usize,
+    f: F,
+) -> (ThreadId, JoinHandle<usize>)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    spawn_supervised_with(name, max_restarts, f, move |_restarts, _payload| {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            "{:?} panicked, restarting ({_restarts}/{max_restarts})",
+            ThreadId::from(std::thread::current().id())
+        );
+    })
+}
+
+/// Like [`spawn_supervised`], but calls `on_restart` on the supervised thread before each
+/// restart, with the restart count so far (starting at 1) and the panic payload.
+///
+/// Nothing is logged, even with the `tracing` feature, so `on_restart` is free to do it.
+///
+/// # Panics
+///
+/// Panics if the OS fails to create the thread, like
+/// [`std::thread::spawn`](https://doc.rust-lang.org/stable/std/thread/fn.spawn.html).
+pub fn spawn_supervised_with<F, R>(
+    name: impl Into<String>,
+    max_restarts: usize,
+    f: F,
+    mut on_restart: R,
+) -> (ThreadId, JoinHandle<usize>)
+where
+    F: Fn() + Send + Sync + 'static,
+    R: FnMut(usize, &(dyn Any + Send)) + Send + 'static,
+{
+    let handle = std::thread::Builder::new()
+        .name(name.into())
+        .spawn(move || {
+            let mut restarts = 0;
+
+            while let Err(payload) = catch_unwind(AssertUnwindSafe(&f)) {
+                if restarts == max_restarts {
+                    resume_unwind(payload);
+                }
+
+                restarts += 1;
+                on_restart(restarts, &*payload);
+            }
+
+            restarts
+        })
+        .expect("failed to spawn thread");
+
+    (handle.thread().id().into(), handle)
+}
+
+/// Spawn a thread running `f` with a stack of `stack_size` bytes, returning its id together
+/// with its handle.
+///
+/// This is [`Builder::stack_size`](https://doc.rust-lang.org/stable/std/thread/struct.Builder.html#method.stack_size)
+/// without the boilerplate. Unlike [`std::thread::spawn`](https://doc.rust-lang.org/stable/std/thread/fn.spawn.html),
+/// failing to create the thread is returned as an error.
+pub fn spawn_with_stack<F, T>(stack_size: usize, f: F) -> AnyRes<(ThreadId, JoinHandle<T>)>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let handle = std::thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(f)
+        .with_context(|| {
+            format!("spawn_with_stack: failed to spawn a thread with a {stack_size} bytes stack")
+        })?;
+
+    Ok((handle.thread().id().into(), handle))
+}
+
+/// A value initialized exactly once per thread.
+///
+/// Unlike the `thread_local!` macro, each `ThreadLocalOnce` is its own key, so it can be a
+/// field of a struct, e.g. for per-thread buffers.
+///
+/// The values are keyed by
+/// [`std::thread::ThreadId`](https://doc.rust-lang.org/stable/std/thread/struct.ThreadId.html),
+/// which is never reused, so a new thread always initializes its own value, even after other
+/// threads have exited. The values of exited threads are kept until the `ThreadLocalOnce` is
+/// dropped.
+#[derive(Default)]
+pub struct ThreadLocalOnce<T: Send>(Mutex<HashMap<std::thread::ThreadId, Box<T>>>);
+
+// Printing the values would read those of other threads, which is only fine for `T: Sync`.
+impl<T: Send> std::fmt::Debug for ThreadLocalOnce<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadLocalOnce").finish_non_exhaustive()
+    }
+}
+
+impl<T: Send> ThreadLocalOnce<T> {
+    /// Create an empty `ThreadLocalOnce`.
+    pub fn new() -> Self {
+        Self(Mutex::new(HashMap::new()))
+    }
+
+    /// Get the value of the current thread, initializing it with `f` first if needed.
+    ///
+    /// `f` runs without holding any lock, so it may use this `ThreadLocalOnce` too. If it
+    /// initializes the value itself, that value is kept and the one returned by `f` dropped.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        let value = f();
+        let mut values = self.0.lock().unwrap();
+        let value: &T = values
+            .entry(std::thread::current().id())
+            .or_insert_with(|| Box::new(value));
+
+        // SAFETY: The value is boxed, and entries are never removed or replaced before `self`
+        // is dropped, so it stays valid at the same address for as long as `&self` does.
+        // Other threads never look it up, so it is only shared if `T: Sync` allows it.
+        unsafe { &*(value as *const T) }
+    }
+
+    /// Get the value of the current thread, if it has been initialized.
+    pub fn get(&self) -> Option<&T> {
+        let values = self.0.lock().unwrap();
+        let value: &T = values.get(&std::thread::current().id())?;
+
+        // SAFETY: See `get_or_init`.
+        Some(unsafe { &*(value as *const T) })
+    }
+}
+
+/// A reusable barrier, like
+/// [`std::sync::Barrier`](https://doc.rust-lang.org/stable/std/sync/struct.Barrier.html),
+/// that also reports which threads took part.
+#[derive(Debug)]
+pub struct IdBarrier {
+    n: usize,
+    state: Mutex<(Vec<ThreadId>, usize)>,
+    cvar: Condvar,
+}
+
+impl IdBarrier {
+    /// Create a barrier that blocks until `n` threads have called [`wait`](Self::wait).
+    ///
+    /// With `n` being 0 or 1, `wait` returns immediately.
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            state: Mutex::new((Vec::new(), 0)),
+            cvar: Condvar::new(),
+        }
+    }
+
+    /// Block until all `n` threads have arrived.
+    ///
+    /// The leader, which is the last thread to arrive, receives the ids of all the
+    /// participating threads in arrival order. The other threads receive an empty `Vec`.
+    pub fn wait(&self) -> Vec<ThreadId> {
+        let mut state = self.state.lock().unwrap();
+        state.0.push(std::thread::current().id().into());
+
+        if state.0.len() >= self.n {
+            state.1 = state.1.wrapping_add(1);
+            self.cvar.notify_all();
+            return std::mem::take(&mut state.0);
+        }
+
+        let generation = state.1;
+        let _state = self
+            .cvar
+            .wait_while(state, |state| state.1 == generation)
+            .unwrap();
+
+        Vec::new()
+    }
+}
+
+const SPIN_LIMIT: u32 = 6;
+const YIELD_LIMIT: u32 = 10;
+
+/// Adaptive backoff for spin loops, similar to
+/// [`crossbeam_utils::Backoff`](https://docs.rs/crossbeam-utils/latest/crossbeam_utils/struct.Backoff.html).
+///
+/// Each step spins exponentially longer, and [`snooze`](Self::snooze) eventually falls back
+/// to [`std::thread::yield_now`](https://doc.rust-lang.org/stable/std/thread/fn.yield_now.html).
+/// Once [`is_completed`](Self::is_completed) returns `true`, callers should switch to a
+/// blocking mechanism such as parking.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    step: Cell<u32>,
+}
+
+impl Backoff {
+    /// Create a new `Backoff`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reset the backoff to its initial state.
+    pub fn reset(&self) {
+        self.step.set(0);
+    }
+
+    /// Back off in a lock-free loop, by spinning only.
+    pub fn spin(&self) {
+        for _ in 0..1 << self.step.get().min(SPIN_LIMIT) {
+            hint::spin_loop();
+        }
+
+        if self.step.get() <= SPIN_LIMIT {
+            self.step.set(self.step.get() + 1);
+        }
+    }
+
+    /// Back off in a blocking loop, spinning first and then yielding the thread.
+    pub fn snooze(&self) {
+        if self.step.get() <= SPIN_LIMIT {
+            for _ in 0..1 << self.step.get() {
+                hint::spin_loop();
+            }
+        } else {
+            std::thread::yield_now();
+        }
+
+        if self.step.get() <= YIELD_LIMIT {
+            self.step.set(self.step.get() + 1);
+        }
+    }
+
+    /// Returns `true` once backing off any further is not worth it, and the caller should block.
+    pub fn is_completed(&self) -> bool {
+        self.step.get() > YIELD_LIMIT
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A minimal fixed-size thread pool.
+///
+/// Jobs are run by the first idle worker. A panicking job does not bring its worker down.
+/// Dropping the pool (or calling [`join`](Self::join)) waits for all the submitted jobs to
+/// finish and then stops the workers.
+#[derive(Debug)]
+pub struct ThreadPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    /// Create a pool with `size` worker threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn new(size: usize) -> Self {
+        assert!(size != 0, "thread pool size must be non-zero");
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = receiver.clone();
+                std::thread::spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+
+                    match job {
+                        Ok(job) => {
+                            let _ = catch_unwind(AssertUnwindSafe(job));
+                        }
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Submit a job to the pool.
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        self.sender
+            .as_ref()
+            .expect("this should be unreachable")
+            .send(Box::new(job))
+            .expect("all the workers have stopped");
+    }
+
+    /// The ids of all the worker threads.
+    pub fn worker_ids(&self) -> Vec<ThreadId> {
+        self.workers
+            .iter()
+            .map(|worker| worker.thread().id().into())
+            .collect()
+    }
+
+    /// Wait for all the submitted jobs to finish, and stop the workers.
+    pub fn join(self) {}
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -30,4 +417,269 @@ mod tests {
         assert_eq!(debug, format!("{:?}", thread_id));
         assert_eq!(debug, format!("ThreadId({})", thread_id));
     }
+
+    #[test]
+    fn thread_pool() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let pool = ThreadPool::new(3);
+        let ids = pool.worker_ids();
+        let count = Arc::new(AtomicUsize::new(0));
+        let ran_on = Arc::new(Mutex::new(Vec::new()));
+
+        assert_eq!(ids.len(), 3);
+        assert!(!ids.contains(&std::thread::current().id().into()));
+
+        pool.execute(|| panic!("boom"));
+
+        for _ in 0..20 {
+            let count = count.clone();
+            let ran_on = ran_on.clone();
+
+            pool.execute(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+                ran_on
+                    .lock()
+                    .unwrap()
+                    .push(ThreadId::from(std::thread::current().id()));
+            });
+        }
+
+        pool.join();
+        assert_eq!(count.load(Ordering::SeqCst), 20);
+        assert!(ran_on.lock().unwrap().iter().all(|id| ids.contains(id)));
+    }
+
+    #[test]
+    fn spawn_supervised() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let (id, handle) = super::spawn_supervised("flaky", 5, move || {
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                panic!("boom");
+            }
+        });
+
+        assert_eq!(id, handle.thread().id().into());
+        assert_eq!(handle.thread().name(), Some("flaky"));
+        assert_eq!(handle.join().ok(), Some(2));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let (_, handle) = super::spawn_supervised("broken", 2, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            panic!("boom");
+        });
+
+        assert!(handle.join().is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn spawn_supervised_with() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let (_, handle) = super::spawn_supervised_with(
+            "observed",
+            3,
+            {
+                let calls = AtomicUsize::new(0);
+                move || {
+                    let calls = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                    if calls <= 2 {
+                        panic!("boom {calls}");
+                    }
+                }
+            },
+            move |restarts, payload| {
+                let msg = payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .unwrap_or_default();
+                log.lock().unwrap().push((restarts, msg));
+            },
+        );
+
+        assert_eq!(handle.join().ok(), Some(2));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [(1, "boom 1".to_string()), (2, "boom 2".to_string())]
+        );
+    }
+
+    #[test]
+    fn parse_thread_id() {
+        assert_eq!(
+            "42".parse::<ThreadId>(),
+            Ok(ThreadId(NonZeroU64::new(42).unwrap()))
+        );
+        assert_eq!("0".parse::<ThreadId>(), Err(ThreadIdParseErr::Zero));
+        assert_eq!("".parse::<ThreadId>(), Err(ThreadIdParseErr::Empty));
+        assert_eq!("abc".parse::<ThreadId>(), Err(ThreadIdParseErr::Invalid));
+        assert_eq!("-1".parse::<ThreadId>(), Err(ThreadIdParseErr::Invalid));
+
+        let id = ThreadId(NonZeroU64::new(7).unwrap());
+        assert_eq!(id.to_string().parse::<ThreadId>(), Ok(id));
+    }
+
+    #[test]
+    fn backoff() {
+        let backoff = Backoff::new();
+
+        for _ in 0..100 {
+            backoff.spin();
+        }
+        assert!(!backoff.is_completed());
+
+        let mut snoozes = 0;
+        while !backoff.is_completed() {
+            backoff.snooze();
+            snoozes += 1;
+        }
+        assert!(snoozes <= YIELD_LIMIT);
+
+        backoff.reset();
+        assert!(!backoff.is_completed());
+    }
+
+    #[test]
+    fn describe_current() {
+        let (id, label) = std::thread::Builder::new()
+            .name("worker-1".into())
+            .spawn(|| {
+                (
+                    ThreadId::from(std::thread::current().id()),
+                    super::describe_current(),
+                )
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(label, format!("ThreadId({id}) [worker-1]"));
+
+        let (id, label) = std::thread::spawn(|| {
+            (
+                ThreadId::from(std::thread::current().id()),
+                super::describe_current(),
+            )
+        })
+        .join()
+        .unwrap();
+        assert_eq!(label, format!("ThreadId({id})"));
+    }
+
+    #[test]
+    fn thread_local_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let local = Arc::new(ThreadLocalOnce::new());
+        let inits = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(std::sync::Barrier::new(4));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let (local, inits, barrier) = (local.clone(), inits.clone(), barrier.clone());
+
+                std::thread::spawn(move || {
+                    assert_eq!(local.get(), None);
+
+                    for _ in 0..3 {
+                        let value = local.get_or_init(|| {
+                            inits.fetch_add(1, Ordering::SeqCst);
+                            (i, ThreadId::from(std::thread::current().id()))
+                        });
+                        assert_eq!(value.0, i);
+                    }
+
+                    // Keep every thread alive until all of them are initialized.
+                    barrier.wait();
+                    *local.get().unwrap()
+                })
+            })
+            .collect();
+
+        let values: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(inits.load(Ordering::SeqCst), 4);
+        assert_eq!(local.get(), None);
+
+        for (i, (j, id)) in values.iter().enumerate() {
+            assert_eq!(i, *j);
+            assert_eq!(values.iter().filter(|(_, other)| other == id).count(), 1);
+        }
+    }
+
+    #[test]
+    fn thread_local_once_after_exit() {
+        let local = Arc::new(ThreadLocalOnce::new());
+
+        for i in 0..8 {
+            let local = local.clone();
+
+            std::thread::spawn(move || {
+                assert!(local.get().is_none());
+                let value = local.get_or_init(|| Cell::new(i));
+                value.set(value.get() * 10);
+                assert_eq!(local.get().unwrap().get(), i * 10);
+            })
+            .join()
+            .unwrap();
+        }
+
+        assert_eq!(local.0.lock().unwrap().len(), 8);
+        assert_eq!(
+            local
+                .get_or_init(|| Cell::new(local.get_or_init(|| Cell::new(1)).get() + 1))
+                .get(),
+            1
+        );
+    }
+
+    #[test]
+    fn spawn_with_stack() {
+        fn depth(n: u64) -> u64 {
+            let buf = hint::black_box([n; 128]);
+
+            match n {
+                0 => 0,
+                _ => 1 + hint::black_box(depth(buf[0] - 1)),
+            }
+        }
+
+        // Each frame holds a 1 KiB buffer that `black_box` keeps on the stack, so this needs
+        // at least 4 MiB, beyond the 2 MiB default of spawned threads.
+        let (id, handle) = super::spawn_with_stack(16 << 20, || depth(4_000)).unwrap();
+        assert_eq!(id, handle.thread().id().into());
+        assert_eq!(handle.join().unwrap(), 4_000);
+    }
+
+    #[test]
+    fn id_barrier() {
+        let barrier = Arc::new(IdBarrier::new(5));
+
+        for _ in 0..2 {
+            let handles: Vec<_> = (0..5)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    std::thread::spawn(move || barrier.wait())
+                })
+                .collect();
+            let mut ids: Vec<ThreadId> = handles.iter().map(|h| h.thread().id().into()).collect();
+            let mut results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+            results.sort_by_key(Vec::len);
+            assert!(results[..4].iter().all(Vec::is_empty));
+
+            let mut seen = results.pop().unwrap();
+            seen.sort_by_key(|id| id.0);
+            ids.sort_by_key(|id| id.0);
+            assert_eq!(seen, ids);
+        }
+
+        let id = ThreadId::from(std::thread::current().id());
+        assert_eq!(IdBarrier::new(1).wait(), vec![id]);
+    }
 }